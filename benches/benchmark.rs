@@ -1,12 +1,33 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use s7::algorithm::encode::encode;
+use s7::algorithm::decode::decode;
+use s7::algorithm::encode::{encode, encode_tiled};
 use s7::read_png::read_png;
+use s7::serialization::read::read_from;
+use s7::serialization::write::write_to;
+use std::io::Cursor;
 
 fn benchmark(c: &mut Criterion) {
     let image = read_png("benches/input.png");
 
     c.bench_function("get edge paths", |b| {
-        b.iter_with_setup(|| image.clone(), |img| encode(img))
+        b.iter_with_setup(|| image.clone(), encode)
+    });
+
+    // Entities are serialized once, decoding takes a fresh copy each time
+    let serialize = |entity| {
+        let mut cursor = Cursor::new(Vec::new());
+        write_to(&mut cursor, entity);
+        cursor.into_inner()
+    };
+    let data = serialize(encode(image.clone()));
+    let tiled_data = serialize(encode_tiled(image.clone(), 256));
+
+    c.bench_function("decode", |b| {
+        b.iter_with_setup(|| read_from(&mut Cursor::new(&data)).unwrap(), decode)
+    });
+
+    c.bench_function("decode tiled", |b| {
+        b.iter_with_setup(|| read_from(&mut Cursor::new(&tiled_data)).unwrap(), decode)
     });
 }
 
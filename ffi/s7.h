@@ -0,0 +1,72 @@
+#ifndef S7_H
+#define S7_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum S7Error {
+  S7_ERROR_OK = 0,
+  S7_ERROR_NULL_POINTER = 1,
+  S7_ERROR_INVALID_ARGUMENT = 2,
+  S7_ERROR_PARSE = 3,
+  S7_ERROR_PANIC = 4,
+  S7_ERROR_LIMIT = 5,
+  S7_ERROR_BUFFER_SIZE = 6,
+} S7Error;
+
+typedef enum S7PixelFormat {
+  S7_PIXEL_FORMAT_RGB = 0,
+  S7_PIXEL_FORMAT_RGBA = 1,
+} S7PixelFormat;
+
+typedef struct S7Buffer {
+  uint8_t *data;
+  uintptr_t len;
+} S7Buffer;
+
+/**
+ * # Safety
+ *
+ * `pixels` must point to `len` readable bytes and `out` must point to a writable `S7Buffer`.
+ * The buffer written to `out` has to be released with `s7_buffer_free`.
+ */
+enum S7Error s7_encode(const uint8_t *pixels,
+                       uintptr_t len,
+                       uint32_t width,
+                       uint32_t height,
+                       uint32_t format,
+                       struct S7Buffer *out);
+
+/**
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes, `out` must point to a writable `S7Buffer`
+ * and `width` and `height` must be writable or null.
+ * The buffer written to `out` has to be released with `s7_buffer_free`.
+ */
+enum S7Error s7_decode(const uint8_t *data,
+                       uintptr_t len,
+                       uint32_t format,
+                       struct S7Buffer *out,
+                       uint32_t *width,
+                       uint32_t *height);
+
+/**
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes and `width` and `height` must be writable.
+ */
+enum S7Error s7_dimensions(const uint8_t *data, uintptr_t len, uint32_t *width, uint32_t *height);
+
+/**
+ * # Safety
+ *
+ * `buffer` must be null or point to an `S7Buffer` filled by this library.
+ */
+void s7_buffer_free(struct S7Buffer *buffer);
+
+#endif /* S7_H */
@@ -0,0 +1,93 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "s7.h"
+
+#define WIDTH 64
+#define HEIGHT 48
+
+#define CHECK(expr)                                                   \
+  do {                                                                \
+    if (!(expr)) {                                                    \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+              #expr);                                                 \
+      return 1;                                                       \
+    }                                                                 \
+  } while (0)
+
+int main(void) {
+  static uint8_t pixels[WIDTH * HEIGHT * 4];
+
+  /* White background with a red rectangle */
+  for (int y = 0; y < HEIGHT; y++) {
+    for (int x = 0; x < WIDTH; x++) {
+      uint8_t *pixel = &pixels[(y * WIDTH + x) * 4];
+      int inside = x >= 10 && x < 30 && y >= 8 && y < 20;
+
+      pixel[0] = 255;
+      pixel[1] = inside ? 0 : 255;
+      pixel[2] = inside ? 0 : 255;
+      pixel[3] = 255;
+    }
+  }
+
+  S7Buffer encoded;
+  CHECK(s7_encode(pixels, sizeof(pixels), WIDTH, HEIGHT, S7_PIXEL_FORMAT_RGBA,
+                  &encoded) == S7_ERROR_OK);
+  CHECK(encoded.data != NULL && encoded.len > 0);
+
+  uint32_t width = 0;
+  uint32_t height = 0;
+  CHECK(s7_dimensions(encoded.data, encoded.len, &width, &height) ==
+        S7_ERROR_OK);
+  CHECK(width == WIDTH && height == HEIGHT);
+
+  S7Buffer decoded;
+  CHECK(s7_decode(encoded.data, encoded.len, S7_PIXEL_FORMAT_RGBA, &decoded,
+                  &width, &height) == S7_ERROR_OK);
+  CHECK(decoded.len == sizeof(pixels));
+  CHECK(memcmp(decoded.data, pixels, sizeof(pixels)) == 0);
+
+  /* Errors, written to their own buffer so that the results above aren't
+     overwritten before being freed */
+  S7Buffer failed;
+  CHECK(s7_encode(pixels, 10, WIDTH, HEIGHT, S7_PIXEL_FORMAT_RGBA, &failed) ==
+        S7_ERROR_BUFFER_SIZE);
+  CHECK(failed.data == NULL);
+  CHECK(s7_encode(pixels, sizeof(pixels), 70000, 1, S7_PIXEL_FORMAT_RGBA,
+                  &failed) == S7_ERROR_INVALID_ARGUMENT);
+  CHECK(s7_decode(pixels, sizeof(pixels), S7_PIXEL_FORMAT_RGB, &failed, NULL,
+                  NULL) == S7_ERROR_PARSE);
+  CHECK(failed.data == NULL);
+  CHECK(s7_encode(pixels, sizeof(pixels), WIDTH, HEIGHT, 7, &failed) ==
+        S7_ERROR_INVALID_ARGUMENT);
+  CHECK(s7_decode(encoded.data, encoded.len, 7, &failed, NULL, NULL) ==
+        S7_ERROR_INVALID_ARGUMENT);
+  CHECK(failed.data == NULL);
+  CHECK(s7_dimensions(NULL, 0, &width, &height) == S7_ERROR_NULL_POINTER);
+
+  /* Dimensions beyond the default read limits */
+  static uint8_t oversized[4096];
+  CHECK(encoded.len <= sizeof(oversized));
+  memcpy(oversized, encoded.data, encoded.len);
+  for (size_t i = 0; i + 6 <= encoded.len; i++) {
+    if (memcmp(&oversized[i], "WIDT", 4) == 0 ||
+        memcmp(&oversized[i], "HEIG", 4) == 0) {
+      oversized[i + 4] = 0xff;
+      oversized[i + 5] = 0xff;
+    }
+  }
+  CHECK(s7_dimensions(oversized, encoded.len, &width, &height) ==
+        S7_ERROR_LIMIT);
+  CHECK(s7_decode(oversized, encoded.len, S7_PIXEL_FORMAT_RGB, &failed, NULL,
+                  NULL) == S7_ERROR_LIMIT);
+  CHECK(failed.data == NULL);
+
+  s7_buffer_free(&encoded);
+  s7_buffer_free(&decoded);
+  s7_buffer_free(&failed);
+  CHECK(encoded.data == NULL && decoded.data == NULL);
+
+  printf("ok\n");
+  return 0;
+}
@@ -0,0 +1,73 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use s7::algorithm::decode::{
+    decode_into, decode_region, decode_rows, decode_scaled, decode_with_limits, PixelFormat,
+};
+use s7::entity::Region;
+use s7::limits::ReadLimits;
+use s7::serialization::read::read_from_with_limits;
+use std::convert::Infallible;
+use std::io::Cursor;
+
+// Small limits keep each run fast, larger inputs only take longer to decode
+const LIMITS: ReadLimits = ReadLimits {
+    max_chunk_size: 1 << 20,
+    max_pixels: 1 << 20,
+    max_objects: 1 << 12,
+    max_points: 1 << 12,
+};
+
+// Every other way of decoding has to give the same pixels as a full decode
+fuzz_target!(|data: &[u8]| {
+    let entity = match read_from_with_limits(&mut Cursor::new(data), LIMITS) {
+        Ok(entity) => entity,
+        Err(_) => return,
+    };
+    if LIMITS.check_entity(&entity).is_err() {
+        return;
+    }
+
+    let (width, height) = (entity.header_chunk.width, entity.header_chunk.height);
+    let row_length = width as usize * 3;
+
+    let region = Region {
+        x: width / 4,
+        y: height / 4,
+        width: width / 2,
+        height: height / 2,
+    };
+    let region_image = decode_region(&entity, region);
+
+    let mut rows = Vec::new();
+    let _ = decode_rows(&entity, |band| {
+        rows.extend_from_slice(band);
+        Ok::<(), Infallible>(())
+    });
+
+    // Rows are padded to check that the padding is left alone
+    let stride = row_length + 1;
+    let mut buffer = vec![0xab; stride * height as usize];
+    decode_into(&entity, &mut buffer, stride, PixelFormat::Rgb).unwrap();
+
+    let _ = decode_scaled(&entity, 0.5, false);
+    let _ = decode_scaled(&entity, 0.25, true);
+
+    let image = decode_with_limits(entity, &LIMITS).unwrap();
+
+    for (x, y, pixel) in region_image.enumerate_pixels() {
+        assert_eq!(
+            pixel,
+            image.get_pixel(region.x as u32 + x, region.y as u32 + y)
+        );
+    }
+
+    assert_eq!(&rows[..], &image.as_raw()[..]);
+
+    for (row, image_row) in buffer
+        .chunks(stride)
+        .zip(image.as_raw().chunks(row_length.max(1)))
+    {
+        assert_eq!(&row[..row_length], image_row);
+        assert_eq!(row[row_length], 0xab);
+    }
+});
@@ -0,0 +1,35 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use s7::entity::Region;
+use s7::serialization::read::{read_chunks_in_rect, read_from, ChunkReader, StreamedChunk};
+use s7::serialization::slice_read::SliceReader;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = read_from(&mut Cursor::new(data));
+
+    if let Ok(mut chunk_reader) = ChunkReader::new(Cursor::new(data)) {
+        while let Some(Ok(chunk)) = chunk_reader.next_streamed() {
+            if let StreamedChunk::Data(objects) = chunk {
+                for object in objects {
+                    let _ = object;
+                }
+            }
+        }
+    }
+
+    if let Ok(reader) = SliceReader::new(data) {
+        for attribute in reader.header_attributes() {
+            let _ = attribute;
+        }
+        let _ = reader.read_entity();
+    }
+
+    let rect = Region {
+        x: 0,
+        y: 0,
+        width: 64,
+        height: 64,
+    };
+    let _ = read_chunks_in_rect(&mut Cursor::new(data), rect);
+});
@@ -1,38 +1,1151 @@
-use crate::entity::{Entity, Object};
-use crate::utils::blank_image;
-use crate::utils::convert_points;
-use image::RgbImage;
-use imageproc::drawing::{draw_hollow_rect_mut, draw_polygon_mut};
-use imageproc::rect::Rect;
+use super::buffer_error::BufferError;
+use super::rasterize::{fill_path, fill_rect, fill_rings, VisibleRows};
+use super::scale::{scale_object, scale_region, scale_symbol};
+use crate::entity::{CopyObject, DataChunk, Entity, LinearGradientObject, Object, Region, Symbol};
+use crate::limits::{LimitError, ReadLimits};
+use crate::s7_image::{Coords, Rgb};
+use crate::utils::are_disjoint;
+use crate::utils::background_image;
+use crate::utils::get_bounds;
+use crate::utils::get_object_bounds;
+use image::{GenericImage, GrayImage, Luma, RgbImage};
+use imageproc::drawing::Canvas;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread;
+use std::vec;
 
-pub fn decode(entity: Entity) -> RgbImage {
-    let header_chunk = entity.header_chunk;
+// Draws the path into a mask covering its bounding box, the position of the
+// mask in the image is returned alongside it
+pub(crate) fn path_mask(points: &[Coords]) -> (Coords, GrayImage) {
+    path_mask_within(points, (u32::MAX, u32::MAX))
+}
 
-    let mut image = blank_image(header_chunk.width, header_chunk.height);
+// Same as path_mask, but the mask only covers the part of the bounding box
+// inside of an image of the given size, which paths in files can lie far
+// outside of
+fn path_mask_within(points: &[Coords], (width, height): (u32, u32)) -> (Coords, GrayImage) {
+    let ((min_x, min_y), (max_x, max_y)) = get_bounds(points);
 
-    for data_chunk in entity.data_chunks {
-        for object in data_chunk.objects {
-            match object {
-                Object::Path(path) => {
-                    let mut points = convert_points(path.points);
-                    if points[0] == points[points.len() - 1] {
-                        points = points.iter().skip(1).map(|x| *x).collect();
+    let mask_width = (max_x as u32 + 1).min(width).saturating_sub(min_x as u32);
+    let mask_height = (max_y as u32 + 1).min(height).saturating_sub(min_y as u32);
+
+    let mut mask = GrayImage::new(mask_width, mask_height);
+    let mask_points: Vec<Coords> = points.iter().map(|(x, y)| (x - min_x, y - min_y)).collect();
+    fill_path(&mut mask, &mask_points, Luma([1]));
+
+    ((min_x, min_y), mask)
+}
+
+// Interpolates between the colors by projecting the coordinates onto the
+// gradient line, using only integer math so that decoding is exact
+pub(crate) fn gradient_color(gradient: &LinearGradientObject, coords: Coords) -> Rgb {
+    let (start_x, start_y) = (gradient.start.0 as i64, gradient.start.1 as i64);
+    let dx = gradient.end.0 as i64 - start_x;
+    let dy = gradient.end.1 as i64 - start_y;
+
+    let length = dx * dx + dy * dy;
+    if length == 0 {
+        return gradient.start_color;
+    }
+
+    let projection = (coords.0 as i64 - start_x) * dx + (coords.1 as i64 - start_y) * dy;
+    let t = projection.max(0).min(length);
+
+    let start_color: [u8; 3] = gradient.start_color.into();
+    let end_color: [u8; 3] = gradient.end_color.into();
+
+    let mut color = [0u8; 3];
+    for i in 0..3 {
+        let mixed = start_color[i] as i64 * (length - t) + end_color[i] as i64 * t;
+        color[i] = ((mixed + length / 2) / length) as u8;
+    }
+
+    color.into()
+}
+
+pub(crate) fn draw_object<C>(image: &mut C, object: &Object, symbols: &[Symbol])
+where
+    C: Canvas<Pixel = image::Rgb<u8>> + VisibleRows,
+{
+    match object {
+        Object::Path(path) => fill_path(image, &path.points, path.color.into()),
+        Object::CompoundPath(path) => {
+            fill_rings(image, &path.rings, path.fill_rule, path.color.into())
+        }
+        Object::Rect(rect) => {
+            fill_rect(
+                image,
+                (rect.x, rect.y),
+                (rect.width, rect.height),
+                rect.color.into(),
+            );
+        }
+        Object::PixelBlock(block) => {
+            let rows = image.visible_rows();
+            let y_start = (block.y as u32).max(rows.start);
+            let y_end = (block.y as u32 + block.height as u32)
+                .min(rows.end)
+                .min(image.height());
+
+            for y in y_start..y_end {
+                let row_start = (y - block.y as u32) as usize * block.width as usize;
+
+                for x in 0..block.width {
+                    let index = (row_start + x as usize) * 3;
+                    let pixel = match block.pixels.get(index..index + 3) {
+                        Some(pixel) => pixel,
+                        None => return,
+                    };
+
+                    let x = block.x as u32 + x as u32;
+                    if x < image.width() {
+                        image.draw_pixel(x, y, image::Rgb([pixel[0], pixel[1], pixel[2]]));
                     }
+                }
+            }
+        }
+        Object::LinearGradient(gradient) => {
+            if gradient.points.is_empty() {
+                return;
+            }
+
+            let ((min_x, min_y), mask) = path_mask_within(&gradient.points, image.dimensions());
+
+            for (x, y, pixel) in mask.enumerate_pixels() {
+                let x = min_x as u32 + x;
+                let y = min_y as u32 + y;
+
+                if pixel.0[0] == 0 || x >= image.width() || y >= image.height() {
+                    continue;
+                }
+
+                let color = gradient_color(gradient, (x as u16, y as u16));
+                image.draw_pixel(x, y, color.into());
+            }
+        }
+        Object::Copy(copy) => {
+            let src = copy.src;
+            let (dst_x, dst_y) = copy.dst;
+
+            // Clip both regions to the image
+            let width = (src.width as u32)
+                .min(image.width().saturating_sub(src.x as u32))
+                .min(image.width().saturating_sub(dst_x as u32));
+            let height = (src.height as u32)
+                .min(image.height().saturating_sub(src.y as u32))
+                .min(image.height().saturating_sub(dst_y as u32));
+
+            if width == 0 || height == 0 {
+                return;
+            }
+
+            // The regions may overlap, so copy the whole source first
+            let pixels: Vec<image::Rgb<u8>> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| image.get_pixel(src.x as u32 + x, src.y as u32 + y))
+                .collect();
+
+            let coords = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+            for ((x, y), pixel) in coords.zip(pixels) {
+                image.draw_pixel(dst_x as u32 + x, dst_y as u32 + y, pixel);
+            }
+        }
+        Object::Symbol(placement) => {
+            let symbol = match symbols.get(placement.symbol as usize) {
+                Some(symbol) => symbol,
+                None => return,
+            };
+
+            let coords = (0..symbol.height).flat_map(|y| (0..symbol.width).map(move |x| (x, y)));
+
+            for ((x, y), is_set) in coords.zip(&symbol.mask) {
+                let x = placement.x as u32 + x as u32;
+                let y = placement.y as u32 + y as u32;
+
+                if *is_set && x < image.width() && y < image.height() {
+                    image.draw_pixel(x, y, placement.color.into());
+                }
+            }
+        }
+        Object::Runs(runs) => {
+            let rows = image.visible_rows();
+
+            for run in &runs.runs {
+                if run.y as u32 >= image.height() || !rows.contains(&(run.y as u32)) {
+                    continue;
+                }
+
+                let x_end = (run.x_start as u32 + run.length as u32).min(image.width());
+                for x in run.x_start as u32..x_end {
+                    image.draw_pixel(x, run.y as u32, run.color.into());
+                }
+            }
+        }
+    }
+}
+
+// Canvas clipping drawing to the extent of a data chunk, pixels outside of the
+// extent read as the background
+pub(crate) struct ClipCanvas<'a, C> {
+    canvas: &'a mut C,
+    extent: Option<Region>,
+    background: image::Rgb<u8>,
+}
+
+impl<'a, C> ClipCanvas<'a, C> {
+    pub(crate) fn new(canvas: &'a mut C, extent: Option<Region>, background: Rgb) -> Self {
+        Self {
+            canvas,
+            extent,
+            background: background.into(),
+        }
+    }
+
+    fn contains(&self, x: u32, y: u32) -> bool {
+        match self.extent {
+            Some(extent) => {
+                x >= extent.x as u32
+                    && y >= extent.y as u32
+                    && x < extent.x as u32 + extent.width as u32
+                    && y < extent.y as u32 + extent.height as u32
+            }
+            None => true,
+        }
+    }
+}
+
+impl<C: Canvas<Pixel = image::Rgb<u8>>> Canvas for ClipCanvas<'_, C> {
+    type Pixel = image::Rgb<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.canvas.dimensions()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        if self.contains(x, y) {
+            self.canvas.get_pixel(x, y)
+        } else {
+            self.background
+        }
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, color: Self::Pixel) {
+        if self.contains(x, y) {
+            self.canvas.draw_pixel(x, y, color);
+        }
+    }
+}
+
+impl<C: VisibleRows> VisibleRows for ClipCanvas<'_, C> {
+    fn visible_rows(&self) -> Range<u32> {
+        self.canvas.visible_rows()
+    }
+}
+
+fn draw_data_chunk<C>(canvas: &mut C, data_chunk: &DataChunk, entity: &Entity)
+where
+    C: Canvas<Pixel = image::Rgb<u8>> + VisibleRows,
+{
+    let background = entity.header_chunk.background();
+    let mut canvas = ClipCanvas::new(canvas, data_chunk.extent, background);
+
+    for object in &data_chunk.objects {
+        draw_object(&mut canvas, object, entity.symbols());
+    }
+}
+
+fn has_independent_chunks(entity: &Entity) -> bool {
+    let extents: Vec<Option<Region>> = entity
+        .data_chunks
+        .iter()
+        .map(|data_chunk| data_chunk.extent)
+        .collect();
+
+    extents.len() > 1 && are_disjoint(&extents)
+}
+
+// Decodes a chunk on its own, into a tile covering its extent
+fn decode_tile(entity: &Entity, data_chunk: &DataChunk) -> Option<(Coords, RgbImage)> {
+    let header_chunk = &entity.header_chunk;
+    let extent = data_chunk.extent?;
+
+    let width = extent
+        .width
+        .min(header_chunk.width.saturating_sub(extent.x));
+    let height = extent
+        .height
+        .min(header_chunk.height.saturating_sub(extent.y));
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let background = header_chunk.background();
+    let mut tile = RgbImage::from_pixel(width as u32, height as u32, background.into());
+
+    let mut canvas = RegionCanvas {
+        buffer: &mut tile,
+        x: extent.x as u32,
+        y: extent.y as u32,
+        image_size: (header_chunk.width as u32, header_chunk.height as u32),
+        background: background.into(),
+    };
 
-                    if points.len() == 1 {
-                        let pt = points[0];
-                        draw_hollow_rect_mut(
-                            &mut image,
-                            Rect::at(pt.x, pt.y).of_size(1, 1),
-                            path.color.into(),
-                        );
-                    } else {
-                        draw_polygon_mut(&mut image, &points, path.color.into());
+    for object in &data_chunk.objects {
+        draw_object(&mut canvas, object, entity.symbols());
+    }
+
+    Some(((extent.x, extent.y), tile))
+}
+
+fn decode_tiles(entity: &Entity, image: &mut RgbImage, threads: usize) {
+    let data_chunks = &entity.data_chunks;
+    let next_index = AtomicUsize::new(0);
+
+    let tiles: Vec<(Coords, RgbImage)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut tiles = Vec::new();
+
+                    while let Some(data_chunk) =
+                        data_chunks.get(next_index.fetch_add(1, atomic::Ordering::Relaxed))
+                    {
+                        tiles.extend(decode_tile(entity, data_chunk));
                     }
+
+                    tiles
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    for ((x, y), tile) in tiles {
+        image.copy_from(&tile, x as u32, y as u32).unwrap();
+    }
+}
+
+pub fn decode(entity: Entity) -> RgbImage {
+    let header_chunk = &entity.header_chunk;
+
+    let mut image = background_image(header_chunk);
+
+    let threads = thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(entity.data_chunks.len());
+
+    // Independent chunks are decoded on multiple threads
+    if threads > 1 && has_independent_chunks(&entity) {
+        decode_tiles(&entity, &mut image, threads);
+        return image;
+    }
+
+    for data_chunk in &entity.data_chunks {
+        draw_data_chunk(&mut image, data_chunk, &entity);
+    }
+
+    image
+}
+
+// Checks the entity against the limits before allocating the image, for
+// entities from untrusted sources
+pub fn decode_with_limits(entity: Entity, limits: &ReadLimits) -> Result<RgbImage, LimitError> {
+    limits.check_entity(&entity)?;
+
+    Ok(decode(entity))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    Rgb,
+    Rgba,
+    Bgra,
+}
+
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Rgb => 3,
+            Self::Rgba | Self::Bgra => 4,
+        }
+    }
+}
+
+// Canvas drawing into the rows of a caller provided buffer, which may be
+// longer than a row of pixels
+struct BufferCanvas<'a> {
+    buffer: &'a mut [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl BufferCanvas<'_> {
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.stride + x as usize * self.format.channels()
+    }
+}
+
+impl Canvas for BufferCanvas<'_> {
+    type Pixel = image::Rgb<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        let pixel = &self.buffer[self.index(x, y)..];
+
+        match self.format {
+            PixelFormat::Rgb | PixelFormat::Rgba => image::Rgb([pixel[0], pixel[1], pixel[2]]),
+            PixelFormat::Bgra => image::Rgb([pixel[2], pixel[1], pixel[0]]),
+        }
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, color: Self::Pixel) {
+        let index = self.index(x, y);
+        let [r, g, b] = color.0;
+
+        let pixel = &mut self.buffer[index..index + self.format.channels()];
+        match self.format {
+            PixelFormat::Rgb => pixel.copy_from_slice(&[r, g, b]),
+            PixelFormat::Rgba => pixel.copy_from_slice(&[r, g, b, 255]),
+            PixelFormat::Bgra => pixel.copy_from_slice(&[b, g, r, 255]),
+        }
+    }
+}
+
+impl VisibleRows for BufferCanvas<'_> {
+    fn visible_rows(&self) -> Range<u32> {
+        0..self.height
+    }
+}
+
+// Decodes into an existing buffer, where each row of pixels starts stride
+// bytes after the previous one. Bytes past the end of a row are left as is.
+pub fn decode_into(
+    entity: &Entity,
+    buffer: &mut [u8],
+    stride: usize,
+    format: PixelFormat,
+) -> Result<(), BufferError> {
+    let header_chunk = &entity.header_chunk;
+    let width = header_chunk.width as u32;
+    let height = header_chunk.height as u32;
+
+    let row_length = width as usize * format.channels();
+    if stride < row_length {
+        return Err(BufferError::new(&format!(
+            "Stride {} is shorter than a row of {} bytes",
+            stride, row_length
+        )));
+    }
+
+    // Large strides can overflow the size of the buffer they describe
+    let required_length = match height {
+        0 => Some(0),
+        _ => (height as usize - 1)
+            .checked_mul(stride)
+            .and_then(|length| length.checked_add(row_length)),
+    };
+    let required_length = required_length.ok_or_else(|| {
+        BufferError::new(&format!(
+            "Buffer for {} rows with a stride of {} is larger than the address space",
+            height, stride
+        ))
+    })?;
+
+    if buffer.len() < required_length {
+        return Err(BufferError::new(&format!(
+            "Buffer of {} bytes is too small for {} rows with a stride of {}",
+            buffer.len(),
+            height,
+            stride
+        )));
+    }
+
+    let mut canvas = BufferCanvas {
+        buffer,
+        width,
+        height,
+        stride,
+        format,
+    };
+
+    fill_rect(
+        &mut canvas,
+        (0, 0),
+        (header_chunk.width, header_chunk.height),
+        header_chunk.background().into(),
+    );
+
+    for data_chunk in &entity.data_chunks {
+        draw_data_chunk(&mut canvas, data_chunk, entity);
+    }
+
+    Ok(())
+}
+
+// Each pixel of an antialiased image averages up to this many samples per axis
+const ANTIALIAS_SAMPLES: u32 = 4;
+
+fn scaled_size(size: u16, scale: f32) -> u32 {
+    ((size as f32 * scale).round() as u32).max(1)
+}
+
+// Rasterizes the objects at a different size, instead of decoding at the
+// full size and resizing the image. Scaled objects have 16 bit coordinates
+// like any other, so the scaled image has to fit into 16 bits per side and
+// antialiasing takes fewer samples where more wouldn't fit.
+pub fn decode_scaled(
+    entity: &Entity,
+    scale: f32,
+    antialias: bool,
+) -> Result<RgbImage, BufferError> {
+    let header_chunk = &entity.header_chunk;
+    let width = scaled_size(header_chunk.width, scale);
+    let height = scaled_size(header_chunk.height, scale);
+
+    let max_samples = u16::MAX as u32 / width.max(height);
+    if max_samples == 0 {
+        return Err(BufferError::new(&format!(
+            "Scaled size {}x{} does not fit into 16 bits",
+            width, height
+        )));
+    }
+
+    let samples = if antialias {
+        ANTIALIAS_SAMPLES.min(max_samples)
+    } else {
+        1
+    };
+    let sample_scale = scale * samples as f32;
+
+    let symbols: Vec<Symbol> = entity
+        .symbols()
+        .iter()
+        .map(|symbol| scale_symbol(symbol, sample_scale))
+        .collect();
+
+    let mut image = RgbImage::from_pixel(
+        width * samples,
+        height * samples,
+        header_chunk.background().into(),
+    );
+
+    for data_chunk in &entity.data_chunks {
+        let extent = data_chunk
+            .extent
+            .map(|extent| scale_region(extent, sample_scale));
+        let mut canvas = ClipCanvas::new(&mut image, extent, header_chunk.background());
+
+        for object in &data_chunk.objects {
+            draw_object(&mut canvas, &scale_object(object, sample_scale), &symbols);
+        }
+    }
+
+    if samples == 1 {
+        return Ok(image);
+    }
+
+    let mut averaged_image = RgbImage::new(width, height);
+    for (x, y, pixel) in averaged_image.enumerate_pixels_mut() {
+        let mut sums = [0u32; 3];
+
+        for sample_y in y * samples..(y + 1) * samples {
+            for sample_x in x * samples..(x + 1) * samples {
+                let sample = image.get_pixel(sample_x, sample_y);
+                for (sum, value) in sums.iter_mut().zip(sample.0.iter()) {
+                    *sum += *value as u32;
                 }
             }
         }
+
+        let count = samples * samples;
+        *pixel = image::Rgb(sums.map(|sum| ((sum + count / 2) / count) as u8));
+    }
+
+    Ok(averaged_image)
+}
+
+// Canvas covering a part of the image, which has the size of the whole image so
+// that drawing clips the same way as on the whole image. Pixels outside of the
+// buffer read as the background and aren't drawn.
+struct RegionCanvas<'a> {
+    buffer: &'a mut RgbImage,
+    x: u32,
+    y: u32,
+    image_size: (u32, u32),
+    background: image::Rgb<u8>,
+}
+
+impl RegionCanvas<'_> {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x - self.x < self.buffer.width()
+            && y - self.y < self.buffer.height()
+    }
+}
+
+impl Canvas for RegionCanvas<'_> {
+    type Pixel = image::Rgb<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image_size
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        if !self.contains(x, y) {
+            return self.background;
+        }
+
+        *self.buffer.get_pixel(x - self.x, y - self.y)
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, color: Self::Pixel) {
+        if self.contains(x, y) {
+            self.buffer.put_pixel(x - self.x, y - self.y, color);
+        }
+    }
+}
+
+impl VisibleRows for RegionCanvas<'_> {
+    fn visible_rows(&self) -> Range<u32> {
+        self.y..self.y + self.buffer.height()
+    }
+}
+
+fn intersects(bounds: (Coords, Coords), region: (Coords, Coords)) -> bool {
+    let ((min_x, min_y), (max_x, max_y)) = bounds;
+    let ((region_min_x, region_min_y), (region_max_x, region_max_y)) = region;
+
+    min_x <= region_max_x && max_x >= region_min_x && min_y <= region_max_y && max_y >= region_min_y
+}
+
+// Decodes only the objects drawing to the region, the result is the same as
+// cropping the fully decoded image
+pub fn decode_region(entity: &Entity, region: Region) -> RgbImage {
+    decode_region_before(entity, region, usize::MAX)
+}
+
+// Objects of all data chunks in drawing order, with the extent of their chunk
+fn chunk_objects(entity: &Entity) -> impl Iterator<Item = (Option<Region>, &Object)> {
+    entity.data_chunks.iter().flat_map(|data_chunk| {
+        data_chunk
+            .objects
+            .iter()
+            .map(move |object| (data_chunk.extent, object))
+    })
+}
+
+// Decodes the region as it is before the object with the given index in
+// drawing order is drawn
+fn decode_region_before(entity: &Entity, region: Region, object_count: usize) -> RgbImage {
+    let header_chunk = &entity.header_chunk;
+    let symbols = entity.symbols();
+
+    let mut image = RgbImage::from_pixel(
+        region.width as u32,
+        region.height as u32,
+        header_chunk.background().into(),
+    );
+
+    // Parts outside of the image stay filled with the background
+    let width = region
+        .width
+        .min(header_chunk.width.saturating_sub(region.x));
+    let height = region
+        .height
+        .min(header_chunk.height.saturating_sub(region.y));
+    if width == 0 || height == 0 {
+        return image;
+    }
+
+    // Copies into the region need their sources decoded as well, which may in
+    // turn be copied from elsewhere, so grow the decoded area backwards
+    let mut area = (
+        (region.x, region.y),
+        (region.x + width - 1, region.y + height - 1),
+    );
+
+    let copies: Vec<&Object> = chunk_objects(entity)
+        .take(object_count)
+        .map(|(_, object)| object)
+        .filter(|object| matches!(object, Object::Copy(_)))
+        .collect();
+
+    for object in copies.into_iter().rev() {
+        let copy = match object {
+            Object::Copy(copy) => copy,
+            _ => continue,
+        };
+
+        let src = copy.src;
+        let src_max_x = (src.x as u32 + src.width as u32).min(header_chunk.width as u32);
+        let src_max_y = (src.y as u32 + src.height as u32).min(header_chunk.height as u32);
+
+        let intersects_area =
+            get_object_bounds(object, symbols).is_some_and(|bounds| intersects(bounds, area));
+
+        if !intersects_area || src_max_x <= src.x as u32 || src_max_y <= src.y as u32 {
+            continue;
+        }
+
+        let ((min_x, min_y), (max_x, max_y)) = area;
+        area = (
+            (min_x.min(src.x), min_y.min(src.y)),
+            (
+                max_x.max(src_max_x as u16 - 1),
+                max_y.max(src_max_y as u16 - 1),
+            ),
+        );
+    }
+
+    let ((min_x, min_y), (max_x, max_y)) = area;
+    let mut area_image = RgbImage::from_pixel(
+        (max_x - min_x) as u32 + 1,
+        (max_y - min_y) as u32 + 1,
+        header_chunk.background().into(),
+    );
+
+    let mut canvas = RegionCanvas {
+        buffer: &mut area_image,
+        x: min_x as u32,
+        y: min_y as u32,
+        image_size: (header_chunk.width as u32, header_chunk.height as u32),
+        background: header_chunk.background().into(),
+    };
+
+    let mut object_index = 0;
+    for data_chunk in &entity.data_chunks {
+        let mut canvas = ClipCanvas::new(&mut canvas, data_chunk.extent, header_chunk.background());
+
+        for object in &data_chunk.objects {
+            if object_index >= object_count {
+                break;
+            }
+            object_index += 1;
+
+            let is_visible =
+                get_object_bounds(object, symbols).is_some_and(|bounds| intersects(bounds, area));
+
+            if is_visible {
+                draw_object(&mut canvas, object, symbols);
+            }
+        }
+    }
+
+    let offset_x = (region.x - min_x) as u32;
+    let offset_y = (region.y - min_y) as u32;
+    for y in 0..height as u32 {
+        for x in 0..width as u32 {
+            image.put_pixel(x, y, *area_image.get_pixel(offset_x + x, offset_y + y));
+        }
     }
 
     image
 }
+
+const STREAMING_BAND_HEIGHT: u16 = 16;
+
+fn band_index(y: u32) -> u32 {
+    y / STREAMING_BAND_HEIGHT as u32
+}
+
+// Size of the copy clipped to the image, the same way as drawing the copy does
+fn clipped_copy_size(copy: &CopyObject, (image_width, image_height): (u32, u32)) -> (u32, u32) {
+    let src = copy.src;
+    let (dst_x, dst_y) = (copy.dst.0 as u32, copy.dst.1 as u32);
+
+    let width = (src.width as u32)
+        .min(image_width.saturating_sub(src.x as u32))
+        .min(image_width.saturating_sub(dst_x));
+    let height = (src.height as u32)
+        .min(image_height.saturating_sub(src.y as u32))
+        .min(image_height.saturating_sub(dst_y));
+
+    (width, height)
+}
+
+// Copy drawing to a later band than the one holding the start of its source
+struct CapturedCopy<'a> {
+    index: usize,
+    extent: Option<Region>,
+    copy: &'a CopyObject,
+    src_rows: Range<u32>,
+    width: u32,
+}
+
+// Source rows of copies which are drawn in a later band than the one holding
+// the source. Each row is taken from its band right before the copy would be
+// drawn there, so it holds what a full decode copies, and is dropped once the
+// copy has drawn it. Only the sources of copies whose destination is still to
+// come are kept, which is at most the sources of all copies crossing a band.
+struct CopySources<'a> {
+    pending: Peekable<vec::IntoIter<CapturedCopy<'a>>>,
+    active: Vec<CapturedCopy<'a>>,
+    captured: usize,
+    band: Range<u32>,
+    background: Rgb,
+    rows: HashMap<(usize, u32), Vec<image::Rgb<u8>>>,
+}
+
+impl<'a> CopySources<'a> {
+    fn new(entity: &'a Entity) -> Self {
+        let header_chunk = &entity.header_chunk;
+        let image_size = (header_chunk.width as u32, header_chunk.height as u32);
+
+        let mut copies: Vec<CapturedCopy> = chunk_objects(entity)
+            .enumerate()
+            .filter_map(|(index, (extent, object))| match object {
+                Object::Copy(copy) => Some((index, extent, copy)),
+                _ => None,
+            })
+            .filter_map(|(index, extent, copy)| {
+                let (width, height) = clipped_copy_size(copy, image_size);
+                let src_y = copy.src.y as u32;
+                let dst_y = copy.dst.1 as u32;
+
+                let is_captured = width > 0
+                    && height > 0
+                    && band_index(dst_y + height - 1) > band_index(src_y)
+                    && dst_y > src_y;
+
+                is_captured.then_some(CapturedCopy {
+                    index,
+                    extent,
+                    copy,
+                    src_rows: src_y..src_y + height,
+                    width,
+                })
+            })
+            .collect();
+        copies.sort_by_key(|copy| (copy.src_rows.start, copy.index));
+
+        Self {
+            pending: copies.into_iter().peekable(),
+            active: Vec::new(),
+            captured: 0,
+            band: 0..0,
+            background: header_chunk.background(),
+            rows: HashMap::new(),
+        }
+    }
+
+    fn start_band(&mut self, band: Range<u32>) {
+        while let Some(copy) = self.pending.next_if(|copy| copy.src_rows.start < band.end) {
+            self.active.push(copy);
+        }
+        self.active.retain(|copy| copy.src_rows.end > band.start);
+        self.active.sort_by_key(|copy| copy.index);
+
+        self.captured = 0;
+        self.band = band;
+    }
+
+    // Takes the source rows in the band of the copies up to the object with
+    // the given index, before that object is drawn
+    fn capture_before(&mut self, object_index: usize, canvas: &mut RegionCanvas) {
+        while let Some(captured) = self.active.get(self.captured) {
+            if captured.index > object_index {
+                break;
+            }
+            self.captured += 1;
+
+            let copy = captured.copy;
+            let canvas = ClipCanvas::new(canvas, captured.extent, self.background);
+            let src_columns = copy.src.x as u32..copy.src.x as u32 + captured.width;
+
+            let start = captured.src_rows.start.max(self.band.start);
+            let end = captured.src_rows.end.min(self.band.end);
+
+            for y in start..end {
+                let dst_y = y - copy.src.y as u32 + copy.dst.1 as u32;
+
+                if band_index(dst_y) > band_index(y) {
+                    let row = src_columns.clone().map(|x| canvas.get_pixel(x, y));
+                    self.rows.insert((captured.index, y), row.collect());
+                }
+            }
+        }
+    }
+
+    fn take_row(&mut self, object_index: usize, y: u32) -> Vec<image::Rgb<u8>> {
+        self.rows
+            .remove(&(object_index, y))
+            .expect("Copied rows are captured in the band holding them")
+    }
+}
+
+// Draws the rows of the copy which lie in the band. Sources in the band are
+// read as they are and sources in rows which have already been written from
+// the captured rows. Sources below the band, which the encoder never writes,
+// are decoded again from the objects drawn before the copy.
+fn draw_copy_rows(
+    canvas: &mut ClipCanvas<RegionCanvas>,
+    entity: &Entity,
+    sources: &mut CopySources,
+    copy: &CopyObject,
+    object_index: usize,
+    band: Range<u32>,
+) {
+    let image_size = (
+        entity.header_chunk.width as u32,
+        entity.header_chunk.height as u32,
+    );
+    let src = copy.src;
+    let (dst_x, dst_y) = (copy.dst.0 as u32, copy.dst.1 as u32);
+    let (width, height) = clipped_copy_size(copy, image_size);
+
+    let rows = dst_y.max(band.start)..(dst_y + height).min(band.end);
+    if width == 0 || rows.is_empty() {
+        return;
+    }
+
+    let src_y = src.y as u32 + rows.start - dst_y;
+    let src_rows = src_y..src_y + rows.len() as u32;
+    let src_columns = src.x as u32..src.x as u32 + width;
+
+    // The regions may overlap, so read the whole source first
+    let mut pixels = Vec::with_capacity(width as usize * rows.len());
+
+    for y in src_rows.start..src_rows.end.min(band.end) {
+        if y < band.start {
+            pixels.extend(sources.take_row(object_index, y));
+        } else {
+            pixels.extend(src_columns.clone().map(|x| canvas.get_pixel(x, y)));
+        }
+    }
+
+    if src_rows.end > band.end {
+        let start = src_rows.start.max(band.end);
+        let src_region = Region {
+            x: src.x,
+            y: start as u16,
+            width: width as u16,
+            height: (src_rows.end - start) as u16,
+        };
+
+        let region_image = decode_region_before(entity, src_region, object_index);
+        pixels.extend(region_image.enumerate_pixels().map(|(x, y, pixel)| {
+            if canvas.contains(src.x as u32 + x, start + y) {
+                *pixel
+            } else {
+                canvas.background
+            }
+        }));
+    }
+
+    let coords = rows.flat_map(|y| (0..width).map(move |x| (dst_x + x, y)));
+    for ((x, y), pixel) in coords.zip(pixels) {
+        canvas.draw_pixel(x, y, pixel);
+    }
+}
+
+// Decodes the image in bands of rows from top to bottom, passing the raw RGB
+// rows of each band to the callback. Besides the entity itself, only a band of
+// rows, the objects drawing to it and the captured sources of copies whose
+// destination is further down are held in memory, never the whole image.
+pub fn decode_rows<F, E>(entity: &Entity, mut write_rows: F) -> Result<(), E>
+where
+    F: FnMut(&[u8]) -> Result<(), E>,
+{
+    let header_chunk = &entity.header_chunk;
+    let symbols = entity.symbols();
+    let background = header_chunk.background();
+
+    let mut objects: Vec<(usize, Option<Region>, &Object, u16, u16)> = chunk_objects(entity)
+        .enumerate()
+        .filter_map(|(index, (extent, object))| {
+            let ((_, min_y), (_, max_y)) = get_object_bounds(object, symbols)?;
+            Some((index, extent, object, min_y, max_y))
+        })
+        .collect();
+    objects.sort_by_key(|&(index, _, _, min_y, _)| (min_y, index));
+
+    let mut pending_objects = objects.into_iter().peekable();
+    let mut active_objects = Vec::new();
+    let mut copy_sources = CopySources::new(entity);
+
+    for band_start in (0..header_chunk.height).step_by(STREAMING_BAND_HEIGHT as usize) {
+        let band_height = STREAMING_BAND_HEIGHT.min(header_chunk.height - band_start);
+        let band_end = band_start as u32 + band_height as u32;
+
+        while let Some(object) = pending_objects.next_if(|object| (object.3 as u32) < band_end) {
+            active_objects.push(object);
+        }
+        active_objects.retain(|object| object.4 >= band_start);
+        active_objects.sort_by_key(|object| object.0);
+
+        let mut band = RgbImage::from_pixel(
+            header_chunk.width as u32,
+            band_height as u32,
+            background.into(),
+        );
+        let mut canvas = RegionCanvas {
+            buffer: &mut band,
+            x: 0,
+            y: band_start as u32,
+            image_size: (header_chunk.width as u32, header_chunk.height as u32),
+            background: background.into(),
+        };
+        copy_sources.start_band(band_start as u32..band_end);
+
+        for &(index, extent, object, _, _) in &active_objects {
+            copy_sources.capture_before(index, &mut canvas);
+            let mut canvas = ClipCanvas::new(&mut canvas, extent, background);
+
+            match object {
+                Object::Copy(copy) => draw_copy_rows(
+                    &mut canvas,
+                    entity,
+                    &mut copy_sources,
+                    copy,
+                    index,
+                    band_start as u32..band_end,
+                ),
+                _ => draw_object(&mut canvas, object, symbols),
+            }
+        }
+        copy_sources.capture_before(usize::MAX, &mut canvas);
+
+        write_rows(band.as_raw())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_into, decode_tiles, draw_data_chunk, PixelFormat};
+    use crate::algorithm::encode::encode_tiled;
+    use crate::entity::{CopyObject, DataChunk, Entity, HeaderChunk, Object, RectObject, Region};
+    use crate::s7_image::{Image, Rgb};
+    use crate::utils::background_image;
+    use image::RgbImage;
+
+    fn decode_serial(entity: &Entity) -> RgbImage {
+        let mut image = background_image(&entity.header_chunk);
+        for data_chunk in &entity.data_chunks {
+            draw_data_chunk(&mut image, data_chunk, entity);
+        }
+
+        image
+    }
+
+    // Tiles are decoded on more threads than there are cores, so that the
+    // result doesn't depend on the machine running the tests
+    fn assert_tiles_match(entity: &Entity) {
+        let mut image = background_image(&entity.header_chunk);
+        decode_tiles(entity, &mut image, 2);
+
+        assert_eq!(image, decode_serial(entity));
+    }
+
+    fn rect(color: Rgb, x: u16, y: u16, width: u16, height: u16) -> Object {
+        Object::Rect(RectObject {
+            color,
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    #[test]
+    fn copy_sources_outside_of_the_tile() {
+        let extent = |x| Region {
+            x,
+            y: 0,
+            width: 4,
+            height: 4,
+        };
+
+        let entity = Entity {
+            header_chunk: HeaderChunk {
+                creation_date: None,
+                width: 8,
+                height: 4,
+                background_color: None,
+                other_attributes: Vec::new(),
+            },
+            version: "1.0.0".to_owned(),
+            symbol_chunk: None,
+            data_chunks: vec![
+                DataChunk {
+                    extent: Some(extent(0)),
+                    objects: vec![
+                        rect(Rgb(255, 0, 0), 0, 0, 4, 4),
+                        Object::Copy(CopyObject {
+                            src: Region {
+                                x: 2,
+                                y: 0,
+                                width: 4,
+                                height: 1,
+                            },
+                            dst: (0, 1),
+                        }),
+                    ],
+                },
+                DataChunk {
+                    extent: Some(extent(4)),
+                    objects: vec![rect(Rgb(0, 0, 255), 4, 0, 4, 4)],
+                },
+            ],
+            other_chunks: Vec::new(),
+        };
+
+        assert_tiles_match(&entity);
+        assert_eq!(decode_serial(&entity).get_pixel(2, 1).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn encoded_tiles() {
+        let image = RgbImage::from_fn(96, 64, |x, y| {
+            let value = ((x % 16) * 16 + (y % 16) * 7) as u8;
+            image::Rgb([
+                value,
+                255 - value,
+                if (x / 8 + y / 8) % 2 == 0 { 0 } else { 255 },
+            ])
+        });
+        let entity = encode_tiled(Image::new(image), 32);
+
+        let objects = || entity.data_chunks.iter().flat_map(|chunk| &chunk.objects);
+        assert!(entity.data_chunks.len() > 1);
+        assert!(objects().any(|object| matches!(object, Object::Copy(_))));
+
+        assert_tiles_match(&entity);
+    }
+
+    #[test]
+    fn buffer_size_overflow() {
+        let entity = Entity {
+            header_chunk: HeaderChunk {
+                creation_date: None,
+                width: 2,
+                height: 3,
+                background_color: None,
+                other_attributes: Vec::new(),
+            },
+            version: "1.0.0".to_owned(),
+            symbol_chunk: None,
+            data_chunks: Vec::new(),
+            other_chunks: Vec::new(),
+        };
+
+        let mut buffer = vec![0; 64];
+        assert!(decode_into(&entity, &mut buffer, usize::MAX / 2 + 1, PixelFormat::Rgb).is_err());
+        assert!(decode_into(&entity, &mut buffer, 30, PixelFormat::Rgb).is_err());
+        assert!(decode_into(&entity, &mut buffer, 29, PixelFormat::Rgb).is_ok());
+    }
+}
@@ -1,9 +1,25 @@
+use super::decode::{gradient_color, path_mask};
 use super::neighboring_coords::NeighboringCoords;
-use crate::entity::{DataChunk, Entity, HeaderChunk, Object, PathObject};
-use crate::s7_image::{Coords, Image};
+use super::repeated_blocks::{apply_copies, get_copies};
+use super::symbols::get_symbols;
+use crate::entity::{
+    DataChunk, Entity, HeaderChunk, LinearGradientObject, Object, PathObject, PixelBlockObject,
+    RectObject, Region, Run, RunsObject, Symbol, SymbolChunk, VERSION,
+};
+use crate::s7_image::{Coords, Image, Rgb};
+use crate::serialization::varint::varint_len;
+use crate::utils::get_bounds;
+use image::{GrayImage, Luma};
+use std::collections::{HashMap, HashSet};
+use std::iter;
 use std::ops::Range;
 use std::time::SystemTime;
 
+const PIXEL_BLOCK_SIZE: u16 = 16;
+
+// Rough number of bytes tracing takes per edge pixel, a raw pixel takes 3 bytes
+const TRACE_BYTES_PER_EDGE_PIXEL: usize = 4;
+
 fn has_unique_neighbors(image: &Image, coords: Coords) -> bool {
     for next_coords in NeighboringCoords::all_neighbors(coords) {
         if !image.compare_pixels(next_coords, coords) {
@@ -17,91 +33,336 @@ fn has_unique_neighbors(image: &Image, coords: Coords) -> bool {
 fn is_edge_pixel(image: &Image, coords: Coords) -> bool {
     let (x, y) = coords;
 
-    if x == 0 || x == image.width - 1 || y == 0 || y == image.height - 1 {
-        true
-    } else if has_unique_neighbors(image, coords) {
-        true
-    } else {
-        false
+    x == 0
+        || x == image.width - 1
+        || y == 0
+        || y == image.height - 1
+        || has_unique_neighbors(image, coords)
+}
+
+// Directions in clockwise order, starting to the right
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+// Pixels along the outer boundary of the region containing the start, walking
+// clockwise with the outside of the region on the left. Neighbors are part of
+// the region if their color is close to the pixel they are reached from.
+fn trace_boundary(image: &Image, start_coords: Coords) -> Vec<Coords> {
+    let neighbor = |(x, y): Coords, direction: usize| {
+        let (dx, dy) = DIRECTIONS[direction];
+        let (next_x, next_y) = (x as i32 + dx, y as i32 + dy);
+        if next_x < 0 || next_y < 0 {
+            return None;
+        }
+
+        let next_coords = (next_x as u16, next_y as u16);
+        if image.is_valid_coords(next_coords) && image.compare_pixels((x, y), next_coords) {
+            Some(next_coords)
+        } else {
+            None
+        }
+    };
+
+    let start_direction = (0..4)
+        .find(|direction| neighbor(start_coords, (direction + 3) % 4).is_none())
+        .unwrap_or(0);
+
+    let mut boundary = vec![start_coords];
+    let mut coords = start_coords;
+    let mut direction = start_direction;
+
+    // Walks which don't lead back to the start are cut off where they repeat
+    let mut visited = HashSet::new();
+    visited.insert((start_coords, start_direction));
+
+    loop {
+        // Turn left if possible, otherwise go straight, right or back
+        let next = [3, 0, 1, 2]
+            .iter()
+            .map(|turn| (direction + turn) % 4)
+            .find_map(|next_direction| Some((neighbor(coords, next_direction)?, next_direction)));
+
+        let (next_coords, next_direction) = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        if !visited.insert((next_coords, next_direction)) {
+            break;
+        }
+
+        boundary.push(next_coords);
+        coords = next_coords;
+        direction = next_direction;
+    }
+
+    // The walk ends where it started
+    if boundary.len() > 1 && boundary.last() == Some(&start_coords) {
+        boundary.pop();
     }
+
+    boundary
 }
 
-fn get_next_pixel_coords(image: &Image, coords: Coords) -> Option<Coords> {
-    for neighbor_coords in NeighboringCoords::neighbors(coords) {
-        if !image.is_valid_coords(neighbor_coords) {
-            continue;
+// Boundary pixels where the direction of the boundary changes
+fn corner_points(boundary: &[Coords]) -> Vec<Coords> {
+    let offset = |a: Coords, b: Coords| (b.0 as i32 - a.0 as i32, b.1 as i32 - a.1 as i32);
+
+    let mut points = vec![boundary[0]];
+
+    for index in 1..boundary.len() {
+        let last_coords = boundary[index - 1];
+        let coords = boundary[index];
+        let next_coords = boundary[(index + 1) % boundary.len()];
+
+        if offset(last_coords, coords) != offset(coords, next_coords) {
+            points.push(coords);
         }
+    }
 
-        if image.pixel_is_checked(neighbor_coords) {
-            continue;
+    points
+}
+
+// Twice the signed area of the closed polygon, using the shoelace formula
+fn double_area(points: &[Coords]) -> i64 {
+    let next_points = points.iter().cycle().skip(1);
+
+    points
+        .iter()
+        .zip(next_points)
+        .map(|(a, b)| a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64)
+        .sum()
+}
+
+fn as_rect(path: &PathObject) -> Option<RectObject> {
+    let ((min_x, min_y), (max_x, max_y)) = get_bounds(&path.points);
+
+    let is_corner = |(x, y): Coords| (x == min_x || x == max_x) && (y == min_y || y == max_y);
+
+    // Every edge has to run along the bounding box
+    let next_points = path.points.iter().cycle().skip(1);
+    for (a, b) in path.points.iter().zip(next_points) {
+        if !is_corner(*a) || (a.0 != b.0 && a.1 != b.1) {
+            return None;
+        }
+    }
+
+    // An outline doubling back on itself does not fill the bounding box
+    let rect_area = (max_x - min_x) as i64 * (max_y - min_y) as i64;
+    if double_area(&path.points).abs() != 2 * rect_area {
+        return None;
+    }
+
+    Some(RectObject {
+        color: path.color,
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    })
+}
+
+// Paths without any area only cover the pixels along their edges, which are
+// often stored more compactly as runs. Paths which zigzag along thin diagonal
+// lines are kept, as they take fewer bytes than a run for each row.
+fn as_runs(path: &PathObject) -> Option<RunsObject> {
+    if double_area(&path.points) != 0 {
+        return None;
+    }
+
+    let ((min_x, min_y), mask) = path_mask(&path.points);
+
+    let mut runs = Vec::new();
+
+    for (y, row) in mask.rows().enumerate() {
+        let mut run_start = None;
+
+        for (x, pixel) in row.chain(iter::once(&Luma([0]))).enumerate() {
+            match (pixel.0[0], run_start) {
+                (1, None) => run_start = Some(x),
+                (0, Some(start)) => {
+                    runs.push(Run {
+                        y: min_y + y as u16,
+                        x_start: min_x + start as u16,
+                        length: (x - start) as u16,
+                        color: path.color,
+                    });
+                    run_start = None;
+                }
+                _ => (),
+            }
         }
+    }
+
+    // Sizes of the objects as they are written
+    let path_size = 8 + path.points.len() * 4;
+    let runs_size = 5
+        + varint_len(runs.len() as u64)
+        + runs
+            .iter()
+            .map(|run| {
+                varint_len(run.y as u64)
+                    + varint_len(run.x_start as u64)
+                    + varint_len(run.length as u64)
+                    + 3
+            })
+            .sum::<usize>();
+
+    if runs_size > path_size {
+        return None;
+    }
+
+    Some(RunsObject { runs })
+}
 
-        if !is_edge_pixel(image, neighbor_coords) {
+// Regions following a vertical or horizontal gradient are stored as one,
+// as long as the gradient reproduces every pixel of the region exactly
+fn as_linear_gradient(image: &Image, path: &PathObject) -> Option<LinearGradientObject> {
+    if double_area(&path.points) == 0 {
+        return None;
+    }
+
+    let ((min_x, min_y), mask) = path_mask(&path.points);
+    let (max_x, max_y) = (
+        min_x + mask.width() as u16 - 1,
+        min_y + mask.height() as u16 - 1,
+    );
+
+    let covered = || {
+        mask.enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0[0] == 1)
+            .map(|(x, y, _)| (min_x + x as u16, min_y + y as u16))
+    };
+
+    let first_color = image.get_pixel(covered().next()?);
+    if covered().all(|coords| image.get_pixel(coords) == first_color) {
+        return None;
+    }
+
+    let start = (min_x, min_y);
+
+    for end in [(min_x, max_y), (max_x, min_y)].iter().copied() {
+        if start == end {
             continue;
         }
 
-        if image.compare_pixels(coords, neighbor_coords) {
-            return Some(neighbor_coords);
+        // Position of the coordinates along the gradient
+        let position = |coords: &Coords| {
+            if end.0 == start.0 {
+                coords.1
+            } else {
+                coords.0
+            }
+        };
+
+        let gradient = LinearGradientObject {
+            start,
+            end,
+            start_color: image.get_pixel(covered().min_by_key(position)?),
+            end_color: image.get_pixel(covered().max_by_key(position)?),
+            points: path.points.clone(),
+        };
+
+        if covered().all(|coords| gradient_color(&gradient, coords) == image.get_pixel(coords)) {
+            return Some(gradient);
         }
     }
 
     None
 }
 
-fn get_object(image: &mut Image, start_coords: Coords) -> (Object, (Coords, Coords)) {
-    let mut min_x = 0u16;
-    let mut min_y = 0u16;
-    let mut max_x = 0u16;
-    let mut max_y = 0u16;
-
-    let mut last_coords = start_coords;
-    let mut cur_coords = start_coords;
+// Color the canvas already has where objects are traced, either the background
+// of the whole image or the fill of the enclosing object
+struct Background {
+    color: Rgb,
+    // Offset and mask of the filled pixels, or the whole image
+    mask: Option<(Coords, GrayImage)>,
+}
 
-    let mut points = vec![start_coords];
+impl Background {
+    fn covers(&self, image: &Image, coords: Coords) -> bool {
+        if image.get_pixel(coords) != self.color {
+            return false;
+        }
 
-    loop {
-        let next_coords = match get_next_pixel_coords(image, cur_coords) {
-            Some(coords) => coords,
-            None => {
-                points.push(cur_coords);
-
-                min_x = min_x.min(cur_coords.0);
-                min_y = min_y.min(cur_coords.1);
-                max_x = max_x.max(cur_coords.0);
-                max_y = max_y.max(cur_coords.1);
-
-                let bounds = ((min_x, max_y), (max_x, min_y));
-                let start_pixel = image.get_pixel(start_coords);
-
-                return (
-                    Object::Path(PathObject {
-                        color: start_pixel,
-                        points,
-                    }),
-                    bounds,
-                );
+        match &self.mask {
+            None => true,
+            Some(((min_x, min_y), mask)) => {
+                let (x, y) = (coords.0.wrapping_sub(*min_x), coords.1.wrapping_sub(*min_y));
+                (x as u32) < mask.width()
+                    && (y as u32) < mask.height()
+                    && mask.get_pixel(x as u32, y as u32).0[0] == 1
             }
-        };
+        }
+    }
+}
 
-        image.set_pixel_is_checked(next_coords, true);
+// Marks the pixels connected to the start which the background already covers
+// as checked, so that they are never traced
+fn check_background(image: &mut Image, start_coords: Coords, background: &Background) {
+    let mut stack = vec![start_coords];
+    image.set_pixel_is_checked(start_coords, true);
 
-        if !(cur_coords.0 == last_coords.0 && cur_coords.0 == next_coords.0)
-            && !(cur_coords.1 == last_coords.1 && cur_coords.1 == next_coords.1)
-        {
-            points.push(cur_coords);
+    while let Some(coords) = stack.pop() {
+        for next_coords in NeighboringCoords::neighbors(coords) {
+            if !image.is_valid_coords(next_coords) || image.pixel_is_checked(next_coords) {
+                continue;
+            }
 
-            min_x = min_x.min(cur_coords.0);
-            min_y = min_y.min(cur_coords.1);
-            max_x = max_x.max(cur_coords.0);
-            max_y = max_y.max(cur_coords.1);
+            if background.covers(image, next_coords) {
+                image.set_pixel_is_checked(next_coords, true);
+                stack.push(next_coords);
+            }
         }
+    }
+}
+
+// Traces the object starting at the coordinates, along with its bounds and the
+// area it fills with a single color
+fn get_object(
+    image: &mut Image,
+    start_coords: Coords,
+) -> (Object, (Coords, Coords), Option<Background>) {
+    let boundary = trace_boundary(image, start_coords);
+    for coords in &boundary {
+        image.set_pixel_is_checked(*coords, true);
+    }
+
+    let ((min_x, min_y), (max_x, max_y)) = get_bounds(&boundary);
+    let bounds = ((min_x, max_y), (max_x, min_y));
 
-        last_coords = cur_coords;
-        cur_coords = next_coords;
+    let path = PathObject {
+        color: image.get_pixel(start_coords),
+        points: corner_points(&boundary),
+    };
+
+    if let Some(gradient) = as_linear_gradient(image, &path) {
+        return (Object::LinearGradient(gradient), bounds, None);
     }
+
+    let fill = Background {
+        color: path.color,
+        mask: Some(path_mask(&path.points)),
+    };
+
+    let object = if let Some(rect) = as_rect(&path) {
+        Object::Rect(rect)
+    } else if let Some(runs) = as_runs(&path) {
+        Object::Runs(runs)
+    } else {
+        Object::Path(path)
+    };
+
+    (object, bounds, Some(fill))
 }
 
-fn get_objects(image: &mut Image, x_range: Range<u16>, y_range: Range<u16>) -> Vec<Object> {
+// Regions the background already covers are not traced, at the top level
+// that is the background of the image and inside of objects their fill
+fn get_objects(
+    image: &mut Image,
+    x_range: Range<u16>,
+    y_range: Range<u16>,
+    background: Option<&Background>,
+) -> Vec<Object> {
     let mut objects: Vec<Object> = Vec::new();
 
     for y in y_range {
@@ -112,14 +373,25 @@ fn get_objects(image: &mut Image, x_range: Range<u16>, y_range: Range<u16>) -> V
                 continue;
             }
 
+            if let Some(background) = background.filter(|bg| bg.covers(image, coords)) {
+                check_background(image, coords, background);
+                continue;
+            }
+
             image.set_pixel_is_checked(coords, true);
 
             if is_edge_pixel(image, coords) {
-                let (object, bounds) = get_object(image, coords);
-                objects.push(object);
+                let (object, bounds, fill) = get_object(image, coords);
+
+                // Consecutive runs are merged into a single object
+                match (objects.last_mut(), object) {
+                    (Some(Object::Runs(last)), Object::Runs(runs)) => last.runs.extend(runs.runs),
+                    (_, object) => objects.push(object),
+                }
 
                 let ((min_x, max_y), (max_x, min_y)) = bounds;
-                let mut interior_paths = get_objects(image, min_x..max_x, min_y..max_y);
+                let mut interior_paths =
+                    get_objects(image, min_x..max_x, min_y..max_y, fill.as_ref());
                 objects.append(&mut interior_paths);
             }
         }
@@ -128,25 +400,263 @@ fn get_objects(image: &mut Image, x_range: Range<u16>, y_range: Range<u16>) -> V
     objects
 }
 
-pub fn encode(mut image: Image) -> Entity {
-    let width = image.width.clone();
-    let height = image.height.clone();
+// The most common color in the image
+fn get_background_color(image: &Image) -> Rgb {
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            *counts.entry(image.get_pixel((x, y)).into()).or_default() += 1;
+        }
+    }
+
+    // Ties are broken by the color itself, so that encoding is deterministic
+    counts
+        .into_iter()
+        .max_by_key(|(color, count)| (*count, *color))
+        .map_or(Rgb(255, 255, 255), |(color, _)| color.into())
+}
+
+fn is_noisy_block(image: &Image, x_range: Range<u16>, y_range: Range<u16>) -> bool {
+    let mut edge_pixels = 0;
+
+    for y in y_range.clone() {
+        for x in x_range.clone() {
+            // Pixels at the image border are always edge pixels, so don't count them
+            if x == 0 || x == image.width - 1 || y == 0 || y == image.height - 1 {
+                continue;
+            }
+
+            if has_unique_neighbors(image, (x, y)) {
+                edge_pixels += 1;
+            }
+        }
+    }
+
+    edge_pixels * TRACE_BYTES_PER_EDGE_PIXEL > x_range.len() * y_range.len() * 3
+}
+
+fn take_pixel_block(image: &mut Image, x_range: Range<u16>, y_range: Range<u16>) -> Object {
+    let mut pixels = Vec::with_capacity(x_range.len() * y_range.len() * 3);
+
+    for y in y_range.clone() {
+        for x in x_range.clone() {
+            let color: [u8; 3] = image.get_pixel((x, y)).into();
+            pixels.extend_from_slice(&color);
+
+            image.set_pixel_is_checked((x, y), true);
+        }
+    }
+
+    Object::PixelBlock(PixelBlockObject {
+        x: x_range.start,
+        y: y_range.start,
+        width: x_range.len() as u16,
+        height: y_range.len() as u16,
+        pixels,
+    })
+}
+
+// Stores regions which would take more bytes to trace than their raw pixels
+// as pixel blocks, and marks them as checked so they are not traced
+fn get_pixel_blocks(image: &mut Image) -> Vec<Object> {
+    let mut objects = Vec::new();
+
+    for block_y in (0..image.height).step_by(PIXEL_BLOCK_SIZE as usize) {
+        let y_range = block_y..block_y.saturating_add(PIXEL_BLOCK_SIZE).min(image.height);
+
+        // Neighboring noisy blocks in a row are merged into one object
+        let mut noisy_start = None;
+
+        let block_xs = (0..image.width).step_by(PIXEL_BLOCK_SIZE as usize);
+        for block_x in block_xs.chain(iter::once(image.width)) {
+            let x_range = block_x..block_x.saturating_add(PIXEL_BLOCK_SIZE).min(image.width);
+            let is_noisy = !x_range.is_empty() && is_noisy_block(image, x_range, y_range.clone());
+
+            match (is_noisy, noisy_start) {
+                (true, None) => noisy_start = Some(block_x),
+                (false, Some(start_x)) => {
+                    objects.push(take_pixel_block(image, start_x..block_x, y_range.clone()));
+                    noisy_start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    objects
+}
+
+// Objects of the image, the symbol placements among them refer to the
+// returned symbols
+fn encode_objects(mut image: Image, background_color: Rgb) -> (Vec<Object>, Vec<Symbol>) {
+    let width = image.width;
+    let height = image.height;
+
+    let copies = get_copies(&image);
+
+    // Noisy regions are taken first, so they aren't split into symbol placements
+    let pixel_blocks = get_pixel_blocks(&mut image);
+    let (symbols, placements) = get_symbols(&mut image);
+
+    let background = Background {
+        color: background_color,
+        mask: None,
+    };
+    let mut objects = get_objects(&mut image, 0..width, 0..height, Some(&background));
+
+    // Symbols and pixel blocks are drawn last, over any paths surrounding them
+    objects.extend(placements);
+    objects.extend(pixel_blocks);
 
-    let objects = get_objects(&mut image, 0..width, 0..height);
+    let objects = apply_copies(objects, copies, &symbols);
 
-    let data_chunk = DataChunk { objects };
+    (objects, symbols)
+}
 
+fn new_entity(
+    (width, height): (u16, u16),
+    background_color: Rgb,
+    symbols: Vec<Symbol>,
+    data_chunks: Vec<DataChunk>,
+) -> Entity {
     let header_chunk = HeaderChunk {
         creation_date: Some(SystemTime::now()),
         other_attributes: Vec::new(),
         width,
         height,
+        background_color: Some(background_color),
+    };
+
+    let symbol_chunk = if symbols.is_empty() {
+        None
+    } else {
+        Some(SymbolChunk { symbols })
     };
 
     Entity {
-        version: "1.0.0".to_owned(),
-        data_chunks: vec![data_chunk],
+        version: VERSION.to_owned(),
+        symbol_chunk,
+        data_chunks,
         header_chunk,
         other_chunks: Vec::new(),
     }
 }
+
+pub fn encode(image: Image) -> Entity {
+    let size = (image.width, image.height);
+
+    let background_color = get_background_color(&image);
+    let (objects, symbols) = encode_objects(image, background_color);
+
+    let data_chunk = DataChunk {
+        extent: None,
+        objects,
+    };
+
+    new_entity(size, background_color, symbols, vec![data_chunk])
+}
+
+// Moves an object encoded in a tile to the position of the tile, symbols are
+// renumbered with the indices of the tile's symbols in the entity
+fn translate_object(object: &mut Object, (dx, dy): Coords, symbol_indices: &[u32]) {
+    let translate = |(x, y): &mut Coords| {
+        *x += dx;
+        *y += dy;
+    };
+
+    match object {
+        Object::Path(path) => path.points.iter_mut().for_each(translate),
+        Object::CompoundPath(path) => path.rings.iter_mut().flatten().for_each(translate),
+        Object::Rect(rect) => {
+            rect.x += dx;
+            rect.y += dy;
+        }
+        Object::PixelBlock(block) => {
+            block.x += dx;
+            block.y += dy;
+        }
+        Object::Runs(runs) => {
+            for run in &mut runs.runs {
+                run.x_start += dx;
+                run.y += dy;
+            }
+        }
+        Object::LinearGradient(gradient) => {
+            translate(&mut gradient.start);
+            translate(&mut gradient.end);
+            gradient.points.iter_mut().for_each(translate);
+        }
+        Object::Copy(copy) => {
+            copy.src.x += dx;
+            copy.src.y += dy;
+            translate(&mut copy.dst);
+        }
+        Object::Symbol(placement) => {
+            placement.x += dx;
+            placement.y += dy;
+            placement.symbol = symbol_indices[placement.symbol as usize];
+        }
+    }
+}
+
+// Encodes every tile of the image into its own data chunk, clipped to the
+// tile, so that the chunks can be decoded at the same time
+pub fn encode_tiled(image: Image, tile_size: u16) -> Entity {
+    let tile_size = tile_size.max(1);
+    let background_color = get_background_color(&image);
+
+    // Tiles share a single dictionary of symbols
+    let mut symbols: Vec<Symbol> = Vec::new();
+    let mut symbol_indices: HashMap<(u16, u16, Vec<bool>), u32> = HashMap::new();
+
+    let mut data_chunks = Vec::new();
+
+    for y in (0..image.height).step_by(tile_size as usize) {
+        for x in (0..image.width).step_by(tile_size as usize) {
+            let width = tile_size.min(image.width - x);
+            let height = tile_size.min(image.height - y);
+
+            let tile = image.sub_image((x, y), (width, height));
+            let (mut objects, tile_symbols) = encode_objects(tile, background_color);
+
+            let tile_symbol_indices: Vec<u32> = tile_symbols
+                .into_iter()
+                .map(|symbol| {
+                    let key = (symbol.width, symbol.height, symbol.mask);
+
+                    *symbol_indices.entry(key.clone()).or_insert_with(|| {
+                        let (width, height, mask) = key;
+                        symbols.push(Symbol {
+                            width,
+                            height,
+                            mask,
+                        });
+                        symbols.len() as u32 - 1
+                    })
+                })
+                .collect();
+
+            for object in &mut objects {
+                translate_object(object, (x, y), &tile_symbol_indices);
+            }
+
+            data_chunks.push(DataChunk {
+                extent: Some(Region {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                objects,
+            });
+        }
+    }
+
+    new_entity(
+        (image.width, image.height),
+        background_color,
+        symbols,
+        data_chunks,
+    )
+}
@@ -1,3 +1,8 @@
+pub mod buffer_error;
 pub mod decode;
 pub mod encode;
 mod neighboring_coords;
+mod rasterize;
+mod repeated_blocks;
+mod scale;
+mod symbols;
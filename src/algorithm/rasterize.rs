@@ -0,0 +1,331 @@
+use crate::entity::FillRule;
+use crate::s7_image::Coords;
+use image::GenericImage;
+use imageproc::drawing::Canvas;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+// Rows of the image a canvas holds, rows outside of them are skipped instead
+// of drawing pixels which would be thrown away
+pub(crate) trait VisibleRows {
+    fn visible_rows(&self) -> Range<u32>;
+}
+
+impl<I: GenericImage> VisibleRows for I {
+    fn visible_rows(&self) -> Range<u32> {
+        0..self.height()
+    }
+}
+
+// Edge between two points which isn't horizontal, from top to bottom, with the
+// direction of the original edge for the winding number
+struct Edge {
+    top: (i64, i64),
+    bottom: (i64, i64),
+    winding: i32,
+}
+
+// Position where an edge crosses a row, as the fraction numerator / denominator,
+// with the direction of the edge for the winding number
+struct Crossing {
+    numerator: i64,
+    denominator: i64,
+    winding: i32,
+}
+
+impl Edge {
+    fn new((x0, y0): Coords, (x1, y1): Coords) -> Option<Self> {
+        let (a, b) = ((x0 as i64, y0 as i64), (x1 as i64, y1 as i64));
+
+        match a.1.cmp(&b.1) {
+            Ordering::Less => Some(Self {
+                top: a,
+                bottom: b,
+                winding: 1,
+            }),
+            Ordering::Greater => Some(Self {
+                top: b,
+                bottom: a,
+                winding: -1,
+            }),
+            Ordering::Equal => None,
+        }
+    }
+
+    fn crossing(&self, y: i64) -> Crossing {
+        let (x0, y0) = self.top;
+        let (x1, y1) = self.bottom;
+
+        Crossing {
+            numerator: x0 * (y1 - y0) + (y - y0) * (x1 - x0),
+            denominator: y1 - y0,
+            winding: self.winding,
+        }
+    }
+}
+
+impl Crossing {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.numerator * other.denominator).cmp(&(other.numerator * self.denominator))
+    }
+
+    // First pixel at or right of the crossing
+    fn ceil(&self) -> i64 {
+        -(-self.numerator).div_euclid(self.denominator)
+    }
+
+    // Last pixel at or left of the crossing
+    fn floor(&self) -> i64 {
+        self.numerator.div_euclid(self.denominator)
+    }
+}
+
+fn is_inside(winding: i32, fill_rule: FillRule) -> bool {
+    match fill_rule {
+        FillRule::NonZero => winding != 0,
+        FillRule::EvenOdd => winding % 2 != 0,
+    }
+}
+
+fn ring_edges(ring: &[Coords]) -> impl Iterator<Item = (Coords, Coords)> + '_ {
+    let next_points = ring.iter().cycle().skip(1);
+    ring.iter().copied().zip(next_points.copied())
+}
+
+fn draw_pixel<C: Canvas>(canvas: &mut C, x: i64, y: i64, color: C::Pixel) {
+    let (width, height) = canvas.dimensions();
+
+    if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+        canvas.draw_pixel(x as u32, y as u32, color);
+    }
+}
+
+// Divides rounding halves up, the denominator has to be positive
+fn div_round(numerator: i64, denominator: i64) -> i64 {
+    (2 * numerator + denominator).div_euclid(2 * denominator)
+}
+
+// Pixels along the straight line between the centers of two pixels, taking the
+// closest pixel at every step along the longer axis. The points are ordered
+// first, so that both directions of an edge cover the same pixels.
+fn draw_segment<C: Canvas + VisibleRows>(canvas: &mut C, a: Coords, b: Coords, color: C::Pixel) {
+    let (from, to) = if a <= b { (a, b) } else { (b, a) };
+    let (x0, y0) = (from.0 as i64, from.1 as i64);
+    let (dx, dy) = (to.0 as i64 - x0, to.1 as i64 - y0);
+
+    // Traced paths mostly consist of horizontal and vertical edges
+    if dx == 0 || dy == 0 {
+        fill_rect(
+            canvas,
+            from,
+            (
+                (dx.unsigned_abs() as u16).saturating_add(1),
+                (dy.unsigned_abs() as u16).saturating_add(1),
+            ),
+            color,
+        );
+        return;
+    }
+
+    let steps = dx.abs().max(dy.abs());
+    for step in 0..=steps {
+        let x = x0 + div_round(step * dx, steps);
+        let y = y0 + div_round(step * dy, steps);
+
+        draw_pixel(canvas, x, y, color);
+    }
+}
+
+// Fills pixels whose centers lie inside the rings according to the fill rule,
+// as well as the pixels along the edges of the rings. Traced paths go through
+// the centers of their boundary pixels, so they cover exactly the traced region.
+pub(crate) fn fill_rings<C, R>(canvas: &mut C, rings: &[R], fill_rule: FillRule, color: C::Pixel)
+where
+    C: Canvas + VisibleRows,
+    R: AsRef<[Coords]>,
+{
+    let rings = || rings.iter().map(|ring| ring.as_ref());
+
+    let (width, height) = canvas.dimensions();
+
+    let mut edges: Vec<Edge> = rings()
+        .flat_map(ring_edges)
+        .filter_map(|(a, b)| Edge::new(a, b))
+        .collect();
+    edges.sort_by_key(|edge| edge.top.1);
+
+    let mut active_edges: Vec<&Edge> = Vec::new();
+    let mut next_edges = edges.iter().peekable();
+    let mut crossings = Vec::new();
+
+    let rows = canvas.visible_rows();
+    let min_y = edges.first().map_or(0, |edge| edge.top.1);
+    let max_y = edges.iter().map(|edge| edge.bottom.1).max().unwrap_or(0);
+
+    // Rows are crossed in the half open range of each edge, so that vertices
+    // shared by two edges are only counted once
+    for y in min_y.max(rows.start as i64)..max_y.min(height as i64).min(rows.end as i64) {
+        while let Some(edge) = next_edges.next_if(|edge| edge.top.1 <= y) {
+            active_edges.push(edge);
+        }
+        active_edges.retain(|edge| edge.bottom.1 > y);
+
+        crossings.clear();
+        crossings.extend(active_edges.iter().map(|edge| edge.crossing(y)));
+        crossings.sort_by(|a, b| a.cmp(b));
+
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].winding;
+
+            if !is_inside(winding, fill_rule) {
+                continue;
+            }
+
+            let from = pair[0].ceil().max(0);
+            let to = pair[1].floor().min(width as i64 - 1);
+
+            for x in from..=to {
+                canvas.draw_pixel(x as u32, y as u32, color);
+            }
+        }
+    }
+
+    for ring in rings() {
+        for (a, b) in ring_edges(ring) {
+            draw_segment(canvas, a, b, color);
+        }
+    }
+}
+
+// A single traced path, which covers its boundary pixels and the pixels inside
+pub(crate) fn fill_path<C: Canvas + VisibleRows>(
+    canvas: &mut C,
+    points: &[Coords],
+    color: C::Pixel,
+) {
+    fill_rings(canvas, &[points], FillRule::EvenOdd, color);
+}
+
+pub(crate) fn fill_rect<C: Canvas + VisibleRows>(
+    canvas: &mut C,
+    (x, y): Coords,
+    (width, height): (u16, u16),
+    color: C::Pixel,
+) {
+    let (canvas_width, canvas_height) = canvas.dimensions();
+    let rows = canvas.visible_rows();
+
+    let x_end = (x as u32 + width as u32).min(canvas_width);
+    let y_end = (y as u32 + height as u32).min(canvas_height).min(rows.end);
+
+    for y in (y as u32).max(rows.start)..y_end {
+        for x in x as u32..x_end {
+            canvas.draw_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithm::decode::decode;
+    use crate::algorithm::encode::encode;
+    use crate::entity::Object;
+    use crate::s7_image::Image;
+    use image::{Rgb, RgbImage};
+
+    // Image from rows of characters, '#' is black, 'o' is red and anything
+    // else is white
+    fn image_from_rows(rows: &[&str]) -> RgbImage {
+        let mut image = RgbImage::new(rows[0].len() as u32, rows.len() as u32);
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                let color = match pixel {
+                    '#' => [0, 0, 0],
+                    'o' => [255, 0, 0],
+                    _ => [255, 255, 255],
+                };
+                image.put_pixel(x as u32, y as u32, Rgb(color));
+            }
+        }
+
+        image
+    }
+
+    // Encodes the image and checks that decoding reproduces every pixel, with
+    // the pixels drawn by traced objects rather than stored as they are
+    fn assert_round_trip(rows: &[&str]) {
+        let image = image_from_rows(rows);
+        let entity = encode(Image::new(image.clone()));
+
+        let objects = || entity.data_chunks.iter().flat_map(|chunk| &chunk.objects);
+        assert!(objects().all(|object| !matches!(object, Object::PixelBlock(_))));
+        assert!(objects().count() > 0);
+
+        let decoded = decode(entity);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            assert_eq!(decoded.get_pixel(x, y), pixel, "pixel ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn single_pixel_paths() {
+        assert_round_trip(&[
+            "........", //
+            "..#.....", "........", ".....o..", "........", ".#......",
+        ]);
+    }
+
+    #[test]
+    fn diagonal_edges() {
+        assert_round_trip(&[
+            "............", //
+            ".....##.....",
+            "....####....",
+            "...######...",
+            "..########..",
+            "...######...",
+            "....####....",
+            ".....##.....",
+            "............",
+        ]);
+
+        assert_round_trip(&[
+            "##..........", //
+            ".##.........",
+            "..##........",
+            "...##.......",
+            "....##......",
+            ".....##.....",
+            "......##....",
+        ]);
+    }
+
+    #[test]
+    fn holes() {
+        assert_round_trip(&[
+            "............", //
+            ".##########.",
+            ".#........#.",
+            ".#..oooo..#.",
+            ".#..o..o..#.",
+            ".#..oooo..#.",
+            ".#........#.",
+            ".##########.",
+            "............",
+        ]);
+
+        assert_round_trip(&[
+            "..........", //
+            "...####...",
+            "..#....#..",
+            ".#......#.",
+            ".#..##..#.",
+            ".#......#.",
+            "..#....#..",
+            "...####...",
+            "..........",
+        ]);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::entity::{CopyObject, Object, Region, Symbol};
+use crate::s7_image::{Coords, Image};
+use crate::utils::get_object_bounds;
+use std::collections::{HashMap, VecDeque};
+
+const BLOCK_SIZE: u16 = 16;
+
+// Bases of the polynomial rolling hashes along rows and columns
+const ROW_BASE: u64 = 0x0000_0100_0000_01b3;
+const COLUMN_BASE: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn pixel_value(image: &Image, coords: Coords) -> u64 {
+    let [r, g, b]: [u8; 3] = image.get_pixel(coords).into();
+
+    // Offset by one so that black pixels still change the hash
+    (((r as u64) << 16) | ((g as u64) << 8) | b as u64) + 1
+}
+
+fn power(base: u64, exponent: u16) -> u64 {
+    (0..exponent).fold(1u64, |val, _| val.wrapping_mul(base))
+}
+
+// Hashes of every block wide window in the row
+fn row_hashes(image: &Image, y: u16) -> Vec<u64> {
+    let top_power = power(ROW_BASE, BLOCK_SIZE - 1);
+
+    let mut hash = (0..BLOCK_SIZE).fold(0u64, |hash, x| {
+        hash.wrapping_mul(ROW_BASE)
+            .wrapping_add(pixel_value(image, (x, y)))
+    });
+
+    let mut hashes = vec![hash];
+
+    for x in 0..image.width - BLOCK_SIZE {
+        hash = hash
+            .wrapping_sub(pixel_value(image, (x, y)).wrapping_mul(top_power))
+            .wrapping_mul(ROW_BASE)
+            .wrapping_add(pixel_value(image, (x + BLOCK_SIZE, y)));
+        hashes.push(hash);
+    }
+
+    hashes
+}
+
+fn block_hash(image: &Image, coords: Coords) -> u64 {
+    let (x, y) = coords;
+
+    (y..y + BLOCK_SIZE).fold(0u64, |hash, y| {
+        let row_hash = (x..x + BLOCK_SIZE).fold(0u64, |hash, x| {
+            hash.wrapping_mul(ROW_BASE)
+                .wrapping_add(pixel_value(image, (x, y)))
+        });
+
+        hash.wrapping_mul(COLUMN_BASE).wrapping_add(row_hash)
+    })
+}
+
+fn block_coords(coords: Coords) -> impl Iterator<Item = Coords> {
+    let (x, y) = coords;
+    (y..y + BLOCK_SIZE).flat_map(move |y| (x..x + BLOCK_SIZE).map(move |x| (x, y)))
+}
+
+fn blocks_equal(image: &Image, a: Coords, b: Coords) -> bool {
+    block_coords(a)
+        .zip(block_coords(b))
+        .all(|(a, b)| image.get_pixel(a) == image.get_pixel(b))
+}
+
+// Uniform blocks are cheap to trace, so they are not worth copying
+fn is_candidate_block(image: &Image, coords: Coords) -> bool {
+    let color = image.get_pixel(coords);
+
+    !block_coords(coords).all(|coords| image.get_pixel(coords) == color)
+}
+
+fn merge_copies(copies: Vec<CopyObject>) -> Vec<CopyObject> {
+    let mut merged: Vec<CopyObject> = Vec::new();
+
+    for copy in copies {
+        // Neighboring blocks copied from neighboring sources become one copy
+        if let Some(last) = merged.last_mut() {
+            if last.dst.1 == copy.dst.1
+                && last.dst.0 + last.src.width == copy.dst.0
+                && last.src.y == copy.src.y
+                && last.src.x + last.src.width == copy.src.x
+                && last.src.height == copy.src.height
+            {
+                last.src.width += copy.src.width;
+                continue;
+            }
+        }
+
+        merged.push(copy);
+    }
+
+    merged
+}
+
+// Finds blocks on a grid which exactly repeat a region above them
+pub fn get_copies(image: &Image) -> Vec<CopyObject> {
+    if image.width < BLOCK_SIZE || image.height < BLOCK_SIZE {
+        return Vec::new();
+    }
+
+    let columns = image.width / BLOCK_SIZE;
+    let rows = image.height / BLOCK_SIZE;
+
+    // Blocks waiting for a source, by hash
+    let mut candidates: HashMap<u64, Vec<Coords>> = HashMap::new();
+    for row in 1..rows {
+        for column in 0..columns {
+            let coords = (column * BLOCK_SIZE, row * BLOCK_SIZE);
+
+            if is_candidate_block(image, coords) {
+                candidates
+                    .entry(block_hash(image, coords))
+                    .or_default()
+                    .push(coords);
+            }
+        }
+    }
+
+    let mut copies = Vec::new();
+
+    let column_power = power(COLUMN_BASE, BLOCK_SIZE - 1);
+    let mut window: VecDeque<Vec<u64>> = (0..BLOCK_SIZE).map(|y| row_hashes(image, y)).collect();
+    let mut hashes = window
+        .iter()
+        .fold(vec![0u64; window[0].len()], |hashes, row| {
+            hashes
+                .iter()
+                .zip(row)
+                .map(|(hash, row_hash)| hash.wrapping_mul(COLUMN_BASE).wrapping_add(*row_hash))
+                .collect()
+        });
+
+    for y in 0..=image.height - BLOCK_SIZE {
+        for (x, hash) in hashes.iter().enumerate() {
+            let x = x as u16;
+
+            let blocks = match candidates.get_mut(hash) {
+                Some(blocks) => blocks,
+                None => continue,
+            };
+
+            // Only blocks below the source can use it
+            let matching_index = blocks
+                .iter()
+                .position(|dst| dst.1 >= y + BLOCK_SIZE && blocks_equal(image, (x, y), *dst));
+
+            if let Some(index) = matching_index {
+                let dst = blocks.remove(index);
+
+                copies.push(CopyObject {
+                    src: Region {
+                        x,
+                        y,
+                        width: BLOCK_SIZE,
+                        height: BLOCK_SIZE,
+                    },
+                    dst,
+                });
+            }
+        }
+
+        if y + BLOCK_SIZE == image.height {
+            break;
+        }
+
+        // Roll the window down by one row
+        let top_row = window.pop_front().unwrap();
+        let new_row = row_hashes(image, y + BLOCK_SIZE);
+
+        for ((hash, top), new) in hashes.iter_mut().zip(&top_row).zip(&new_row) {
+            *hash = hash
+                .wrapping_sub(top.wrapping_mul(column_power))
+                .wrapping_mul(COLUMN_BASE)
+                .wrapping_add(*new);
+        }
+
+        window.push_back(new_row);
+    }
+
+    // Copies are drawn in this order, sources above a block are done by then
+    copies.sort_by_key(|copy| (copy.dst.1, copy.dst.0));
+
+    merge_copies(copies)
+}
+
+// Copies are drawn over everything else, so objects inside of their destinations
+// are left out. Copies which don't replace any objects are not worth keeping.
+pub fn apply_copies(
+    objects: Vec<Object>,
+    copies: Vec<CopyObject>,
+    symbols: &[Symbol],
+) -> Vec<Object> {
+    // Index of the copy covering each block on the grid
+    let mut cells: HashMap<Coords, usize> = HashMap::new();
+    for (index, copy) in copies.iter().enumerate() {
+        for column in 0..copy.src.width / BLOCK_SIZE {
+            let (x, y) = copy.dst;
+            cells.insert((x / BLOCK_SIZE + column, y / BLOCK_SIZE), index);
+        }
+    }
+
+    let mut is_used = vec![false; copies.len()];
+
+    let mut objects: Vec<Object> = objects
+        .into_iter()
+        .filter(|object| {
+            let ((min_x, min_y), (max_x, max_y)) = match get_object_bounds(object, symbols) {
+                Some(bounds) => bounds,
+                None => return true,
+            };
+
+            // The object can be left out if copies cover all of it
+            let mut covering = Vec::new();
+            for row in min_y / BLOCK_SIZE..=max_y / BLOCK_SIZE {
+                for column in min_x / BLOCK_SIZE..=max_x / BLOCK_SIZE {
+                    match cells.get(&(column, row)) {
+                        Some(index) => covering.push(*index),
+                        None => return true,
+                    }
+                }
+            }
+
+            for index in covering {
+                is_used[index] = true;
+            }
+
+            false
+        })
+        .collect();
+
+    let copies = copies
+        .into_iter()
+        .zip(is_used)
+        .filter(|(_, is_used)| *is_used)
+        .map(|(copy, _)| Object::Copy(copy));
+
+    objects.extend(copies);
+    objects
+}
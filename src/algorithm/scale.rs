@@ -0,0 +1,173 @@
+use crate::entity::{
+    CompoundPathObject, CopyObject, LinearGradientObject, Object, PathObject, PixelBlockObject,
+    RectObject, Region, Run, RunsObject, Symbol, SymbolObject,
+};
+use crate::s7_image::Coords;
+
+// Scaled pixels are sampled at their centers, an edge between two pixels is
+// scaled directly and the pixels inside the scaled edges are covered
+fn scale_edge(value: u16, scale: f32) -> u16 {
+    (value as f32 * scale).round() as u16
+}
+
+// Points are the centers of pixels
+fn scale_point((x, y): Coords, scale: f32) -> Coords {
+    let scale_center = |value: u16| ((value as f32 + 0.5) * scale - 0.5).round() as u16;
+
+    (scale_center(x), scale_center(y))
+}
+
+// Neighboring points can end up on the same pixel, which polygons can't have
+fn scale_points(points: &[Coords], scale: f32) -> Vec<Coords> {
+    let mut scaled_points: Vec<Coords> = points
+        .iter()
+        .map(|point| scale_point(*point, scale))
+        .collect();
+
+    scaled_points.dedup();
+    if scaled_points.len() > 1 && scaled_points.first() == scaled_points.last() {
+        scaled_points.pop();
+    }
+
+    scaled_points
+}
+
+// Scaled start and size of a range of pixels
+fn scale_range(start: u16, length: u16, scale: f32) -> (u16, u16) {
+    let scaled_start = scale_edge(start, scale);
+    let scaled_end = scale_edge(start.saturating_add(length), scale);
+
+    (scaled_start, scaled_end - scaled_start)
+}
+
+pub(crate) fn scale_region(region: Region, scale: f32) -> Region {
+    let (x, width) = scale_range(region.x, region.width, scale);
+    let (y, height) = scale_range(region.y, region.height, scale);
+
+    Region {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+// Pixel of the unscaled range whose center is closest to the scaled pixel
+fn source_index(index: u16, length: u16, scaled_length: u16) -> usize {
+    let index = ((index as f32 + 0.5) * length as f32 / scaled_length as f32) as usize;
+    index.min(length as usize - 1)
+}
+
+pub(crate) fn scale_symbol(symbol: &Symbol, scale: f32) -> Symbol {
+    let (_, width) = scale_range(0, symbol.width, scale);
+    let (_, height) = scale_range(0, symbol.height, scale);
+
+    let mut mask = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let x = source_index(x, symbol.width, width);
+            let y = source_index(y, symbol.height, height);
+
+            mask.push(symbol.mask.get(x + y * symbol.width as usize) == Some(&true));
+        }
+    }
+
+    Symbol {
+        width,
+        height,
+        mask,
+    }
+}
+
+// The same object in the coordinates of the image scaled by the factor, symbol
+// placements refer to the symbols scaled with scale_symbol
+pub(crate) fn scale_object(object: &Object, scale: f32) -> Object {
+    match object {
+        Object::Path(path) => Object::Path(PathObject {
+            color: path.color,
+            points: scale_points(&path.points, scale),
+        }),
+        Object::CompoundPath(path) => Object::CompoundPath(CompoundPathObject {
+            color: path.color,
+            fill_rule: path.fill_rule,
+            rings: path
+                .rings
+                .iter()
+                .map(|ring| scale_points(ring, scale))
+                .collect(),
+        }),
+        Object::Rect(rect) => {
+            let (x, width) = scale_range(rect.x, rect.width, scale);
+            let (y, height) = scale_range(rect.y, rect.height, scale);
+
+            Object::Rect(RectObject {
+                color: rect.color,
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+        Object::PixelBlock(block) => {
+            let (x, width) = scale_range(block.x, block.width, scale);
+            let (y, height) = scale_range(block.y, block.height, scale);
+
+            let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+            for scaled_y in 0..height {
+                for scaled_x in 0..width {
+                    let index = source_index(scaled_x, block.width, width)
+                        + source_index(scaled_y, block.height, height) * block.width as usize;
+
+                    match block.pixels.get(index * 3..index * 3 + 3) {
+                        Some(pixel) => pixels.extend_from_slice(pixel),
+                        None => pixels.extend_from_slice(&[0, 0, 0]),
+                    }
+                }
+            }
+
+            Object::PixelBlock(PixelBlockObject {
+                x,
+                y,
+                width,
+                height,
+                pixels,
+            })
+        }
+        Object::Runs(runs) => {
+            let mut scaled_runs = Vec::new();
+
+            for run in &runs.runs {
+                let (x_start, length) = scale_range(run.x_start, run.length, scale);
+                let (y, height) = scale_range(run.y, 1, scale);
+
+                for y in y..y.saturating_add(height) {
+                    scaled_runs.push(Run {
+                        y,
+                        x_start,
+                        length,
+                        color: run.color,
+                    });
+                }
+            }
+
+            Object::Runs(RunsObject { runs: scaled_runs })
+        }
+        Object::LinearGradient(gradient) => Object::LinearGradient(LinearGradientObject {
+            start: scale_point(gradient.start, scale),
+            end: scale_point(gradient.end, scale),
+            start_color: gradient.start_color,
+            end_color: gradient.end_color,
+            points: scale_points(&gradient.points, scale),
+        }),
+        Object::Copy(copy) => Object::Copy(CopyObject {
+            src: scale_region(copy.src, scale),
+            dst: (scale_edge(copy.dst.0, scale), scale_edge(copy.dst.1, scale)),
+        }),
+        Object::Symbol(placement) => Object::Symbol(SymbolObject {
+            symbol: placement.symbol,
+            x: scale_edge(placement.x, scale),
+            y: scale_edge(placement.y, scale),
+            color: placement.color,
+        }),
+    }
+}
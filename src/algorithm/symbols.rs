@@ -0,0 +1,240 @@
+use super::neighboring_coords::NeighboringCoords;
+use crate::entity::{Object, Symbol, SymbolObject};
+use crate::s7_image::{Coords, Image, Rgb};
+use crate::utils::get_bounds;
+use std::collections::HashMap;
+
+// Larger components are left to tracing
+const MAX_SYMBOL_SIZE: u16 = 32;
+
+// A symbol is only worth storing in the dictionary if it is placed this often
+const MIN_PLACEMENTS: usize = 2;
+
+struct Component {
+    color: Rgb,
+    // Only kept for components small enough to become symbols
+    pixels: Option<Vec<Coords>>,
+}
+
+// Unchecked pixels of exactly the same color connected to the start, since
+// placements are drawn with a single color
+fn get_component(
+    image: &Image,
+    ids: &mut [Option<usize>],
+    id: usize,
+    start: Coords,
+) -> Vec<Coords> {
+    let index = |(x, y): Coords| x as usize + y as usize * image.width as usize;
+
+    let mut component = Vec::new();
+    let mut stack = vec![start];
+    ids[index(start)] = Some(id);
+
+    while let Some(coords) = stack.pop() {
+        component.push(coords);
+
+        for next_coords in NeighboringCoords::neighbors(coords) {
+            if !image.is_valid_coords(next_coords)
+                || ids[index(next_coords)].is_some()
+                || image.pixel_is_checked(next_coords)
+            {
+                continue;
+            }
+
+            if image.get_pixel(coords) == image.get_pixel(next_coords) {
+                ids[index(next_coords)] = Some(id);
+                stack.push(next_coords);
+            }
+        }
+    }
+
+    component
+}
+
+fn find_group(groups: &mut [usize], id: usize) -> usize {
+    let mut root = id;
+    while groups[root] != root {
+        root = groups[root];
+    }
+
+    groups[id] = root;
+    root
+}
+
+// Clusters small connected components with identical shapes into symbols.
+//
+// Only groups of small components lying within a single larger region are
+// used, like glyphs on a background. Their placed pixels are painted over with
+// the color of that region, so tracing only has to deal with the background.
+// Checked pixels, like those of pixel blocks, are left out.
+pub fn get_symbols(image: &mut Image) -> (Vec<Symbol>, Vec<Object>) {
+    let index = |(x, y): Coords| x as usize + y as usize * image.width as usize;
+
+    let mut ids = vec![None; image.width as usize * image.height as usize];
+    let mut components = Vec::new();
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if ids[index((x, y))].is_some() || image.pixel_is_checked((x, y)) {
+                continue;
+            }
+
+            let pixels = get_component(image, &mut ids, components.len(), (x, y));
+
+            let ((min_x, min_y), (max_x, max_y)) = get_bounds(&pixels);
+            let is_small = max_x - min_x < MAX_SYMBOL_SIZE && max_y - min_y < MAX_SYMBOL_SIZE;
+
+            components.push(Component {
+                color: image.get_pixel((x, y)),
+                pixels: if is_small { Some(pixels) } else { None },
+            });
+        }
+    }
+
+    // Neighboring small components form groups, which have to be surrounded by
+    // exactly one large component
+    let mut groups: Vec<usize> = (0..components.len()).collect();
+    let mut surroundings: Vec<Option<Option<usize>>> = vec![None; components.len()];
+
+    for (id, component) in components.iter().enumerate() {
+        let pixels = match &component.pixels {
+            Some(pixels) => pixels,
+            None => continue,
+        };
+
+        for coords in pixels {
+            for next_coords in NeighboringCoords::neighbors(*coords) {
+                if !image.is_valid_coords(next_coords) {
+                    continue;
+                }
+
+                let next_id = match ids[index(next_coords)] {
+                    Some(next_id) => next_id,
+                    None => continue,
+                };
+
+                if components[next_id].pixels.is_some() {
+                    let (root, next_root) = (
+                        find_group(&mut groups, id),
+                        find_group(&mut groups, next_id),
+                    );
+                    groups[next_root] = root;
+                }
+            }
+        }
+    }
+
+    for (id, component) in components.iter().enumerate() {
+        let pixels = match &component.pixels {
+            Some(pixels) => pixels,
+            None => continue,
+        };
+
+        let root = find_group(&mut groups, id);
+
+        for coords in pixels {
+            for next_coords in NeighboringCoords::neighbors(*coords) {
+                if !image.is_valid_coords(next_coords) {
+                    continue;
+                }
+
+                // Groups next to checked pixels aren't surrounded by a single region
+                let next_id = match ids[index(next_coords)] {
+                    Some(next_id) => next_id,
+                    None => {
+                        surroundings[root] = Some(None);
+                        continue;
+                    }
+                };
+
+                if components[next_id].pixels.is_some() {
+                    continue;
+                }
+
+                surroundings[root] = match surroundings[root] {
+                    None => Some(Some(next_id)),
+                    Some(Some(surrounding)) if surrounding == next_id => Some(Some(surrounding)),
+                    _ => Some(None),
+                };
+            }
+        }
+    }
+
+    // Distinct masks in order of their first placement, with all of their placements
+    let mut clusters: Vec<(Symbol, Vec<(SymbolObject, Rgb)>)> = Vec::new();
+    let mut cluster_indices: HashMap<(u16, u16, Vec<bool>), usize> = HashMap::new();
+
+    for (id, component) in components.iter().enumerate() {
+        let pixels = match &component.pixels {
+            Some(pixels) => pixels,
+            None => continue,
+        };
+
+        let surrounding = match surroundings[find_group(&mut groups, id)] {
+            Some(Some(surrounding)) => &components[surrounding],
+            _ => continue,
+        };
+
+        let ((min_x, min_y), (max_x, max_y)) = get_bounds(pixels);
+        let width = max_x - min_x + 1;
+        let height = max_y - min_y + 1;
+
+        let mut mask = vec![false; width as usize * height as usize];
+        for (x, y) in pixels {
+            mask[(x - min_x) as usize + (y - min_y) as usize * width as usize] = true;
+        }
+
+        let placement = SymbolObject {
+            symbol: 0,
+            x: min_x,
+            y: min_y,
+            color: component.color,
+        };
+
+        let key = (width, height, mask);
+        match cluster_indices.get(&key) {
+            Some(index) => clusters[*index].1.push((placement, surrounding.color)),
+            None => {
+                let (width, height, mask) = key.clone();
+                cluster_indices.insert(key, clusters.len());
+                clusters.push((
+                    Symbol {
+                        width,
+                        height,
+                        mask,
+                    },
+                    vec![(placement, surrounding.color)],
+                ));
+            }
+        }
+    }
+
+    let mut symbols = Vec::new();
+    let mut placements = Vec::new();
+
+    for (symbol, cluster_placements) in clusters {
+        if cluster_placements.len() < MIN_PLACEMENTS {
+            continue;
+        }
+
+        for (mut placement, surrounding_color) in cluster_placements {
+            let coords = (0..symbol.height).flat_map(|y| (0..symbol.width).map(move |x| (x, y)));
+            for ((x, y), is_set) in coords.zip(&symbol.mask) {
+                if *is_set {
+                    image.set_pixel((placement.x + x, placement.y + y), surrounding_color);
+                }
+            }
+
+            placement.symbol = symbols.len() as u32;
+            placements.push(placement);
+        }
+
+        symbols.push(symbol);
+    }
+
+    placements.sort_by_key(|placement| (placement.y, placement.x));
+
+    let objects = placements.into_iter().map(Object::Symbol).collect();
+
+    (symbols, objects)
+}
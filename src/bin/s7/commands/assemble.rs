@@ -0,0 +1,33 @@
+use crate::cli_error::CliError;
+use clap::{App, ArgMatches, SubCommand};
+use s7::serialization::json;
+use s7::serialization::write::write;
+
+pub fn definition<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("assemble")
+        .about("Assembles input JSON to output S7 file")
+        .arg(
+            clap::Arg::with_name("INPUT")
+                .help("Sets the path of the input file")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            clap::Arg::with_name("OUTPUT")
+                .help("Sets the path of the output file")
+                .required(true)
+                .index(2),
+        )
+}
+
+pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+
+    let entity = json::read(input_path).map_err(|err| CliError::new(&format!("{}", err)))?;
+    write(output_path, entity);
+
+    log::info!("Saved output S7 file to {}", output_path);
+
+    Ok(())
+}
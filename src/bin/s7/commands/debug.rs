@@ -2,9 +2,11 @@ use crate::cli_error::CliError;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use image::{Rgb, RgbImage};
 use imageproc::drawing as draw;
-use s7::entity::{Entity, Object};
+use imageproc::rect::Rect;
+use s7::entity::{Entity, LinearGradientObject, Object, PathObject};
+use s7::s7_image::Coords;
 use s7::serialization::read::read;
-use s7::utils::blank_image;
+use s7::utils::background_image;
 use std::convert::From;
 
 enum ColorType {
@@ -42,7 +44,7 @@ pub fn definition<'a>() -> App<'a, 'a> {
             Arg::with_name("outlines")
                 .short("O")
                 .long("outlines")
-                .help("Draws the outlines of each path on a white background"),
+                .help("Draws the outlines of each path on the background"),
         )
         .arg(
             Arg::with_name("outline-color")
@@ -55,6 +57,25 @@ pub fn definition<'a>() -> App<'a, 'a> {
         )
 }
 
+fn draw_path_outline(image: &mut RgbImage, points: &[Coords], color: Rgb<u8>) {
+    let a = points.iter().skip(1);
+    let b = points.iter().take(points.len().saturating_sub(1));
+
+    let lines: Vec<(&Coords, &Coords)> = a.zip(b).collect();
+
+    for (a, b) in lines {
+        let (ax, ay) = a;
+        let (bx, by) = b;
+
+        draw::draw_line_segment_mut(
+            image,
+            (*ax as f32, *ay as f32),
+            (*bx as f32, *by as f32),
+            color,
+        )
+    }
+}
+
 fn draw_outlines(image: &mut RgbImage, entity: &Entity, color_type: ColorType) {
     for data in &entity.data_chunks {
         for (index, object) in &data
@@ -77,24 +98,90 @@ fn draw_outlines(image: &mut RgbImage, entity: &Entity, color_type: ColorType) {
                 }
                 ColorType::Original => match object {
                     Object::Path(path) => path.color.into(),
+                    Object::CompoundPath(path) => path.color.into(),
+                    Object::Rect(rect) => rect.color.into(),
+                    Object::PixelBlock(_) | Object::Copy(_) => Rgb([0, 0, 0]),
+                    Object::LinearGradient(gradient) => gradient.start_color.into(),
+                    Object::Symbol(placement) => placement.color.into(),
+                    Object::Runs(runs) => runs
+                        .runs
+                        .first()
+                        .map_or(Rgb([0, 0, 0]), |run| run.color.into()),
                 },
             };
 
             match object {
-                Object::Path(path) => {
-                    let a = path.points.iter().skip(1);
-                    let b = path.points.iter().take(path.points.len() - 1);
+                Object::Path(PathObject { points, .. })
+                | Object::LinearGradient(LinearGradientObject { points, .. }) => {
+                    draw_path_outline(image, points, color)
+                }
+                Object::CompoundPath(path) => {
+                    for ring in &path.rings {
+                        draw_path_outline(image, ring, color)
+                    }
+                }
+                Object::Rect(rect) => {
+                    if rect.width == 0 || rect.height == 0 {
+                        continue;
+                    }
 
-                    let lines: Vec<(&(u16, u16), &(u16, u16))> = a.zip(b).collect();
+                    draw::draw_hollow_rect_mut(
+                        image,
+                        Rect::at(rect.x as i32, rect.y as i32)
+                            .of_size(rect.width as u32, rect.height as u32),
+                        color,
+                    )
+                }
+                Object::PixelBlock(block) => {
+                    if block.width == 0 || block.height == 0 {
+                        continue;
+                    }
 
-                    for (a, b) in lines {
-                        let (ax, ay) = a;
-                        let (bx, by) = b;
+                    draw::draw_hollow_rect_mut(
+                        image,
+                        Rect::at(block.x as i32, block.y as i32)
+                            .of_size(block.width as u32, block.height as u32),
+                        color,
+                    )
+                }
+                Object::Copy(copy) => {
+                    if copy.src.width == 0 || copy.src.height == 0 {
+                        continue;
+                    }
+
+                    draw::draw_hollow_rect_mut(
+                        image,
+                        Rect::at(copy.dst.0 as i32, copy.dst.1 as i32)
+                            .of_size(copy.src.width as u32, copy.src.height as u32),
+                        color,
+                    )
+                }
+                Object::Symbol(placement) => {
+                    let symbol = match entity.symbols().get(placement.symbol as usize) {
+                        Some(symbol) if symbol.width > 0 && symbol.height > 0 => symbol,
+                        _ => continue,
+                    };
+
+                    draw::draw_hollow_rect_mut(
+                        image,
+                        Rect::at(placement.x as i32, placement.y as i32)
+                            .of_size(symbol.width as u32, symbol.height as u32),
+                        color,
+                    )
+                }
+                Object::Runs(runs) => {
+                    for run in &runs.runs {
+                        if run.length == 0 {
+                            continue;
+                        }
 
                         draw::draw_line_segment_mut(
                             image,
-                            (*ax as f32, *ay as f32),
-                            (*bx as f32, *by as f32),
+                            (run.x_start as f32, run.y as f32),
+                            (
+                                (run.x_start as u32 + run.length as u32 - 1) as f32,
+                                run.y as f32,
+                            ),
                             color,
                         )
                     }
@@ -107,7 +194,7 @@ fn draw_outlines(image: &mut RgbImage, entity: &Entity, color_type: ColorType) {
 pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
     let input_path = matches.value_of("INPUT").unwrap();
     let entity = read(input_path).unwrap();
-    let mut image = blank_image(entity.header_chunk.width, entity.header_chunk.height);
+    let mut image = background_image(&entity.header_chunk);
 
     if matches.is_present("outlines") {
         draw_outlines(
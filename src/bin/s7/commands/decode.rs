@@ -1,8 +1,14 @@
 use crate::cli_error::CliError;
 use clap::{App, ArgMatches, SubCommand};
 use image::ImageFormat;
-use s7::algorithm::decode::decode;
-use s7::serialization::read::read;
+use s7::algorithm::decode::{decode, decode_region};
+use s7::entity::Region;
+use s7::export::{pdf, svg};
+use s7::limits::ReadLimits;
+use s7::serialization::read::{read, read_chunks_in_rect_with_limits};
+use s7::write_png::write_png_streaming;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 
 pub fn definition<'a>() -> App<'a, 'a> {
     SubCommand::with_name("decode")
@@ -19,18 +25,97 @@ pub fn definition<'a>() -> App<'a, 'a> {
                 .required(true)
                 .index(2),
         )
+        .arg(
+            clap::Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets the format of the output file")
+                .takes_value(true)
+                .possible_values(&["png", "svg", "pdf"])
+                .default_value("png"),
+        )
+        .arg(
+            clap::Arg::with_name("crop")
+                .long("crop")
+                .value_name("X,Y,WIDTH,HEIGHT")
+                .help("Only decodes the given region of the image")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("streaming")
+                .long("streaming")
+                .help("Writes the PNG row by row without decoding the whole image in memory")
+                .conflicts_with("crop"),
+        )
+}
+
+fn parse_region(value: &str) -> Result<Region, CliError> {
+    let error = || CliError::new(&format!("Invalid crop region {}", value));
+
+    let values = value
+        .split(',')
+        .map(|part| part.trim().parse::<u16>().map_err(|_| error()))
+        .collect::<Result<Vec<u16>, CliError>>()?;
+
+    match values[..] {
+        [x, y, width, height] => Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(error()),
+    }
 }
 
 pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
     let input_path = matches.value_of("INPUT").unwrap();
     let output_path = matches.value_of("OUTPUT").unwrap();
 
-    let entity = read(input_path).unwrap();
-    let image = decode(entity);
+    let crop = match matches.value_of("crop") {
+        Some(value) => Some(parse_region(value)?),
+        None => None,
+    };
+
+    // Cropping only needs the data chunks drawing to the region
+    let entity = match crop {
+        Some(region) => {
+            let mut reader = BufReader::new(File::open(input_path).unwrap());
+            read_chunks_in_rect_with_limits(&mut reader, region, ReadLimits::unlimited()).unwrap()
+        }
+        None => read(input_path).unwrap(),
+    };
+
+    let streaming = matches.is_present("streaming");
+
+    match matches.value_of("format").unwrap() {
+        "png" if streaming => {
+            let file = File::create(output_path).unwrap();
+            write_png_streaming(BufWriter::new(file), &entity).unwrap();
+        }
+        "png" => {
+            let image = match crop {
+                Some(region) => decode_region(&entity, region),
+                None => decode(entity),
+            };
 
-    image
-        .save_with_format(output_path, ImageFormat::Png)
-        .unwrap();
+            image
+                .save_with_format(output_path, ImageFormat::Png)
+                .unwrap();
+        }
+        _ if crop.is_some() => {
+            return Err(CliError::new("Cropping is only supported for PNG output"))
+        }
+        _ if streaming => return Err(CliError::new("Streaming is only supported for PNG output")),
+        "svg" => {
+            svg::write(output_path, &entity).map_err(|err| CliError::new(&format!("{}", err)))?
+        }
+        "pdf" => {
+            pdf::write(output_path, &entity).map_err(|err| CliError::new(&format!("{}", err)))?
+        }
+        format => return Err(CliError::new(&format!("Unknown format {}", format))),
+    }
 
     Ok(())
 }
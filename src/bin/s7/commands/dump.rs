@@ -0,0 +1,33 @@
+use crate::cli_error::CliError;
+use clap::{App, ArgMatches, SubCommand};
+use s7::serialization::json;
+use s7::serialization::read::read;
+
+pub fn definition<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("dump")
+        .about("Dumps input S7 to output JSON file")
+        .arg(
+            clap::Arg::with_name("INPUT")
+                .help("Sets the path of the input file")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            clap::Arg::with_name("OUTPUT")
+                .help("Sets the path of the output file")
+                .required(true)
+                .index(2),
+        )
+}
+
+pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+
+    let entity = read(input_path).unwrap();
+    json::write(output_path, &entity);
+
+    log::info!("Saved output JSON file to {}", output_path);
+
+    Ok(())
+}
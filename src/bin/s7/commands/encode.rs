@@ -1,6 +1,6 @@
 use crate::cli_error::CliError;
 use clap::{App, ArgMatches, SubCommand};
-use s7::algorithm::encode::encode;
+use s7::algorithm::encode::{encode, encode_tiled};
 use s7::read_png::read_png;
 use s7::serialization::write::write;
 
@@ -19,12 +19,27 @@ pub fn definition<'a>() -> App<'a, 'a> {
                 .required(true)
                 .index(2),
         )
+        .arg(
+            clap::Arg::with_name("tile-size")
+                .long("tile-size")
+                .value_name("SIZE")
+                .help("Encodes tiles of the given size independently, so they can be decoded in parallel")
+                .takes_value(true),
+        )
 }
 
 pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
     let input_path = matches.value_of("INPUT").unwrap();
     let output_path = matches.value_of("OUTPUT").unwrap();
 
+    let tile_size = match matches.value_of("tile-size") {
+        Some(value) => match value.parse::<u16>() {
+            Ok(size) if size > 0 => Some(size),
+            _ => return Err(CliError::new(&format!("Invalid tile size {}", value))),
+        },
+        None => None,
+    };
+
     log::info!("Encoding image at {} into {}", input_path, output_path);
     log::info!("Reading input image...");
 
@@ -32,9 +47,17 @@ pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
 
     log::info!("Encoding image...");
 
-    let entity = encode(input_img);
+    let entity = match tile_size {
+        Some(tile_size) => encode_tiled(input_img, tile_size),
+        None => encode(input_img),
+    };
 
-    log::debug!("{} objects in entity", entity.data_chunks[0].objects.len());
+    let object_count: usize = entity
+        .data_chunks
+        .iter()
+        .map(|data_chunk| data_chunk.objects.len())
+        .sum();
+    log::debug!("{} objects in entity", object_count);
     log::info!("Writing output image...");
 
     write(output_path, entity);
@@ -1,18 +1,28 @@
+#[cfg(feature = "json")]
+mod assemble;
 mod debug;
 mod decode;
+#[cfg(feature = "json")]
+mod dump;
 mod encode;
+mod thumbnail;
 
 use crate::cli_error::CliError;
 use clap::{App, AppSettings, Arg};
 use simplelog::{ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
 
 pub fn definition<'a>() -> App<'a, 'a> {
-    let subcommands = vec![
+    #[allow(unused_mut)]
+    let mut subcommands = vec![
         decode::definition(),
         encode::definition(),
         debug::definition(),
+        thumbnail::definition(),
     ];
 
+    #[cfg(feature = "json")]
+    subcommands.extend(vec![dump::definition(), assemble::definition()]);
+
     let mut subcommands_with_args: Vec<App> = Vec::new();
 
     for sub in subcommands {
@@ -63,6 +73,11 @@ pub fn exec() -> Result<(), CliError> {
         "decode" => decode::exec,
         "encode" => encode::exec,
         "debug" => debug::exec,
+        "thumbnail" => thumbnail::exec,
+        #[cfg(feature = "json")]
+        "dump" => dump::exec,
+        #[cfg(feature = "json")]
+        "assemble" => assemble::exec,
 
         _ => unreachable!(),
     };
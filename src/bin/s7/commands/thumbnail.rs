@@ -0,0 +1,66 @@
+use crate::cli_error::CliError;
+use clap::{App, ArgMatches, SubCommand};
+use image::ImageFormat;
+use s7::algorithm::decode::decode_scaled;
+use s7::serialization::read::read;
+
+// Thumbnails are rasterized in memory at once, so keep them reasonably small
+const MAX_SIZE: u16 = 16384;
+
+pub fn definition<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("thumbnail")
+        .about("Decodes input S7 to a scaled output PNG file")
+        .arg(
+            clap::Arg::with_name("INPUT")
+                .help("Sets the path of the input file")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            clap::Arg::with_name("OUTPUT")
+                .help("Sets the path of the output file")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            clap::Arg::with_name("size")
+                .short("s")
+                .long("size")
+                .value_name("SIZE")
+                .help("Sets the size of the longest side of the thumbnail, up to 16384")
+                .takes_value(true)
+                .default_value("256"),
+        )
+        .arg(
+            clap::Arg::with_name("antialias")
+                .short("a")
+                .long("antialias")
+                .help("Smooths the thumbnail by averaging multiple samples per pixel"),
+        )
+}
+
+pub fn exec(matches: &ArgMatches) -> Result<(), CliError> {
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+
+    let size_value = matches.value_of("size").unwrap();
+    let size = match size_value.parse::<u16>() {
+        Ok(size) if size > 0 && size <= MAX_SIZE => size,
+        _ => return Err(CliError::new(&format!("Invalid size {}", size_value))),
+    };
+
+    let entity = read(input_path).unwrap();
+
+    let header_chunk = &entity.header_chunk;
+    let longest_side = header_chunk.width.max(header_chunk.height).max(1);
+    let scale = size as f32 / longest_side as f32;
+
+    let image = decode_scaled(&entity, scale, matches.is_present("antialias"))
+        .map_err(|err| CliError::new(&format!("{}", err)))?;
+
+    image
+        .save_with_format(output_path, ImageFormat::Png)
+        .unwrap();
+
+    Ok(())
+}
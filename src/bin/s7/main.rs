@@ -1,6 +1,11 @@
 mod cli_error;
 mod commands;
 
+use std::process;
+
 fn main() {
-    commands::exec().unwrap();
+    if let Err(err) = commands::exec() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
 }
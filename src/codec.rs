@@ -0,0 +1,126 @@
+use crate::algorithm::decode::{decode, decode_into, PixelFormat};
+use crate::algorithm::encode::encode;
+use crate::entity::Entity;
+use crate::s7_image::Image;
+use crate::serialization::read::read_from;
+use crate::serialization::write::write_stream;
+use image::error::{
+    DecodingError, EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind,
+};
+use image::{
+    ColorType, DynamicImage, ImageDecoder, ImageEncoder, ImageError, ImageResult, RgbImage,
+};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+fn format_hint() -> ImageFormatHint {
+    ImageFormatHint::Name("S7".to_owned())
+}
+
+pub struct S7Decoder {
+    entity: Entity,
+}
+
+impl S7Decoder {
+    pub fn new<R: Read>(reader: R) -> ImageResult<Self> {
+        let mut reader = BufReader::new(reader);
+        let entity = read_from(&mut reader)
+            .map_err(|err| ImageError::Decoding(DecodingError::new(format_hint(), err)))?;
+
+        Ok(Self { entity })
+    }
+}
+
+impl<'a> ImageDecoder<'a> for S7Decoder {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        let header_chunk = &self.entity.header_chunk;
+        (header_chunk.width as u32, header_chunk.height as u32)
+    }
+
+    fn color_type(&self) -> ColorType {
+        ColorType::Rgb8
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let image = decode(self.entity);
+        Ok(Cursor::new(image.into_raw()))
+    }
+
+    // Decodes straight into the buffer instead of going through a reader
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        let stride = self.entity.header_chunk.width as usize * PixelFormat::Rgb.channels();
+
+        decode_into(&self.entity, buf, stride, PixelFormat::Rgb)
+            .map_err(|err| ImageError::Decoding(DecodingError::new(format_hint(), err)))
+    }
+}
+
+// The image crate can't register other formats, so image::open can't read S7
+// files and these take its place
+pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<DynamicImage> {
+    load(File::open(path)?)
+}
+
+pub fn load<R: Read>(reader: R) -> ImageResult<DynamicImage> {
+    DynamicImage::from_decoder(S7Decoder::new(reader)?)
+}
+
+pub struct S7Encoder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> S7Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> ImageEncoder for S7Encoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let rgb_buf = match color_type {
+            ColorType::Rgb8 => buf.to_vec(),
+            ColorType::Rgba8 => buf
+                .chunks_exact(4)
+                .flat_map(|rgba| rgba[..3].to_vec())
+                .collect(),
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        format_hint(),
+                        UnsupportedErrorKind::Color(color_type.into()),
+                    ),
+                ))
+            }
+        };
+
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(ImageError::Encoding(EncodingError::new(
+                format_hint(),
+                "Image dimensions do not fit into 16 bits",
+            )));
+        }
+
+        let rgb_image = RgbImage::from_raw(width, height, rgb_buf).ok_or_else(|| {
+            ImageError::Encoding(EncodingError::new(
+                format_hint(),
+                "Buffer size does not match image dimensions",
+            ))
+        })?;
+
+        let entity = encode(Image::new(rgb_image));
+
+        write_stream(&mut self.writer, entity)?;
+
+        Ok(())
+    }
+}
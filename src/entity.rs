@@ -1,46 +1,197 @@
-use super::s7_image::Rgb;
+use super::s7_image::{Coords, Rgb};
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
+// Version written by the encoder. Version 2 added the object codes after P,
+// the SYMB and INDX chunks and the FEND payload, version 1 files only contain
+// paths and can still be read.
+pub const VERSION: &str = "2.0.0";
+pub const SUPPORTED_MAJOR_VERSIONS: [u32; 2] = [1, 2];
+
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Entity {
     pub version: String,
     pub header_chunk: HeaderChunk,
+    pub symbol_chunk: Option<SymbolChunk>,
     pub data_chunks: Vec<DataChunk>,
     pub other_chunks: Vec<Chunk>,
 }
 
+impl Entity {
+    pub fn symbols(&self) -> &[Symbol] {
+        match &self.symbol_chunk {
+            Some(symbol_chunk) => &symbol_chunk.symbols,
+            None => &[],
+        }
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Chunk {
     pub code: String,
     pub data: Vec<u8>,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct HeaderAttibute {
     pub key: String,
     pub val: Vec<u8>,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct HeaderChunk {
     pub creation_date: Option<SystemTime>,
     pub width: u16,
     pub height: u16,
+    pub background_color: Option<Rgb>,
     pub other_attributes: Vec<HeaderAttibute>,
 }
 
+impl HeaderChunk {
+    // Images without a background color start out white
+    pub fn background(&self) -> Rgb {
+        self.background_color.unwrap_or(Rgb(255, 255, 255))
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Symbol {
+    pub width: u16,
+    pub height: u16,
+    pub mask: Vec<bool>,
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct SymbolChunk {
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct PathObject {
     pub color: Rgb,
     pub points: Vec<(u16, u16)>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct CompoundPathObject {
+    pub color: Rgb,
+    pub fill_rule: FillRule,
+    pub rings: Vec<Vec<Coords>>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct RectObject {
+    pub color: Rgb,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct PixelBlockObject {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Run {
+    pub y: u16,
+    pub x_start: u16,
+    pub length: u16,
+    pub color: Rgb,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct RunsObject {
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct LinearGradientObject {
+    pub start: Coords,
+    pub end: Coords,
+    pub start_color: Rgb,
+    pub end_color: Rgb,
+    pub points: Vec<Coords>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Region {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct CopyObject {
+    pub src: Region,
+    pub dst: Coords,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct SymbolObject {
+    pub symbol: u32,
+    pub x: u16,
+    pub y: u16,
+    pub color: Rgb,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub enum Object {
     Path(PathObject),
+    Rect(RectObject),
+    PixelBlock(PixelBlockObject),
+    Runs(RunsObject),
+    LinearGradient(LinearGradientObject),
+    Copy(CopyObject),
+    Symbol(SymbolObject),
+    CompoundPath(CompoundPathObject),
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct DataChunk {
+    // Objects are clipped to the extent, pixels outside of it read as the
+    // background, so chunks with disjoint extents can be decoded independently
+    pub extent: Option<Region>,
     pub objects: Vec<Object>,
 }
+
+// Position of a data chunk in the file, relative to the start of the file, as
+// listed by the index chunk
+#[derive(Clone, Copy, Debug)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub size: u32,
+    pub extent: Option<Region>,
+}
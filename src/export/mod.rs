@@ -0,0 +1,205 @@
+use crate::algorithm::decode::{draw_object, ClipCanvas};
+use crate::entity::{Entity, Object, PixelBlockObject};
+use crate::utils::background_image;
+use imageproc::drawing::Canvas;
+
+pub mod pdf;
+pub mod svg;
+
+// Copies refer to already decoded pixels, which other formats can't, so they
+// are exported as pixel blocks holding the pixels they decode to. Copies which
+// are clipped away entirely draw nothing and have no block.
+pub(crate) fn copied_pixel_blocks(entity: &Entity) -> Vec<Option<PixelBlockObject>> {
+    let objects = || {
+        entity
+            .data_chunks
+            .iter()
+            .flat_map(|data_chunk| data_chunk.objects.iter())
+    };
+
+    if !objects().any(|object| matches!(object, Object::Copy(_))) {
+        return Vec::new();
+    }
+
+    let header_chunk = &entity.header_chunk;
+    let mut image = background_image(header_chunk);
+
+    let mut blocks = Vec::new();
+
+    for data_chunk in &entity.data_chunks {
+        let mut canvas = ClipCanvas::new(&mut image, data_chunk.extent, header_chunk.background());
+
+        for object in &data_chunk.objects {
+            draw_object(&mut canvas, object, entity.symbols());
+
+            let copy = match object {
+                Object::Copy(copy) => copy,
+                _ => continue,
+            };
+
+            // Only the part of the destination inside the image and the extent is drawn
+            let (mut x, mut y) = (copy.dst.0 as u32, copy.dst.1 as u32);
+            let mut x_end = (x + copy.src.width as u32).min(header_chunk.width as u32);
+            let mut y_end = (y + copy.src.height as u32).min(header_chunk.height as u32);
+
+            if let Some(extent) = data_chunk.extent {
+                x = x.max(extent.x as u32);
+                y = y.max(extent.y as u32);
+                x_end = x_end.min(extent.x as u32 + extent.width as u32);
+                y_end = y_end.min(extent.y as u32 + extent.height as u32);
+            }
+
+            let width = x_end.saturating_sub(x) as u16;
+            let height = y_end.saturating_sub(y) as u16;
+            if width == 0 || height == 0 {
+                blocks.push(None);
+                continue;
+            }
+
+            let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+            for y in y..y + height as u32 {
+                for x in x..x + width as u32 {
+                    pixels.extend_from_slice(&canvas.get_pixel(x, y).0);
+                }
+            }
+
+            let (x, y) = (x as u16, y as u16);
+
+            blocks.push(Some(PixelBlockObject {
+                x,
+                y,
+                width,
+                height,
+                pixels,
+            }));
+        }
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copied_pixel_blocks, pdf, svg};
+    use crate::entity::{
+        CopyObject, DataChunk, Entity, HeaderChunk, Object, PixelBlockObject, RectObject, Region,
+    };
+    use crate::s7_image::Rgb;
+
+    #[test]
+    fn copies_clipped_away() {
+        let entity = Entity {
+            header_chunk: HeaderChunk {
+                creation_date: None,
+                width: 8,
+                height: 8,
+                background_color: None,
+                other_attributes: Vec::new(),
+            },
+            version: "1.0.0".to_owned(),
+            symbol_chunk: None,
+            data_chunks: vec![DataChunk {
+                extent: None,
+                objects: vec![Object::Copy(CopyObject {
+                    src: Region {
+                        x: 0,
+                        y: 0,
+                        width: 4,
+                        height: 4,
+                    },
+                    dst: (100, 100),
+                })],
+            }],
+            other_chunks: Vec::new(),
+        };
+
+        assert!(copied_pixel_blocks(&entity)[0].is_none());
+
+        let mut data = Vec::new();
+        svg::write_to(&mut data, &entity).unwrap();
+        assert!(!String::from_utf8(data).unwrap().contains("<image"));
+
+        let mut data = Vec::new();
+        pdf::write_to(&mut data, &entity).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("/Subtype /Image"));
+    }
+
+    #[test]
+    fn pdf_pixel_blocks() {
+        let entity = Entity {
+            header_chunk: HeaderChunk {
+                creation_date: None,
+                width: 8,
+                height: 8,
+                background_color: None,
+                other_attributes: Vec::new(),
+            },
+            version: "1.0.0".to_owned(),
+            symbol_chunk: None,
+            data_chunks: vec![DataChunk {
+                extent: None,
+                objects: vec![Object::PixelBlock(PixelBlockObject {
+                    x: 1,
+                    y: 2,
+                    width: 2,
+                    height: 1,
+                    pixels: vec![255, 0, 0, 0, 0, 255],
+                })],
+            }],
+            other_chunks: Vec::new(),
+        };
+
+        let mut data = Vec::new();
+        pdf::write_to(&mut data, &entity).unwrap();
+        let pdf = String::from_utf8_lossy(&data);
+
+        assert!(pdf.contains("q 2 0 0 -1 1 3 cm /I0 Do Q\n"));
+        assert!(pdf.contains("/XObject << /I0 5 0 R >>"));
+        assert!(pdf.contains(
+            "5 0 obj\n<< /Type /XObject /Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode"
+        ));
+    }
+    #[test]
+    fn chunk_extents() {
+        let entity = Entity {
+            header_chunk: HeaderChunk {
+                creation_date: None,
+                width: 8,
+                height: 8,
+                background_color: None,
+                other_attributes: Vec::new(),
+            },
+            version: "1.0.0".to_owned(),
+            symbol_chunk: None,
+            data_chunks: vec![DataChunk {
+                extent: Some(Region {
+                    x: 2,
+                    y: 2,
+                    width: 4,
+                    height: 4,
+                }),
+                objects: vec![Object::Rect(RectObject {
+                    color: Rgb(255, 0, 0),
+                    x: 0,
+                    y: 0,
+                    width: 8,
+                    height: 8,
+                })],
+            }],
+            other_chunks: Vec::new(),
+        };
+
+        let mut data = Vec::new();
+        svg::write_to(&mut data, &entity).unwrap();
+        let svg = String::from_utf8(data).unwrap();
+        assert!(svg.contains(
+            "<clipPath id=\"c0\"><rect x=\"2\" y=\"2\" width=\"4\" height=\"4\"/></clipPath>"
+        ));
+        assert!(svg.contains("<g clip-path=\"url(#c0)\">\n<path d=\"M0 0h8v8h-8Z\""));
+
+        let mut data = Vec::new();
+        pdf::write_to(&mut data, &entity).unwrap();
+        let pdf = String::from_utf8_lossy(&data);
+        assert!(pdf.contains("q 2 2 4 4 re W n\n1.0000 0.0000 0.0000 rg 0 0 8 8 re f\nQ\n"));
+    }
+}
@@ -0,0 +1,357 @@
+use super::copied_pixel_blocks;
+use crate::entity::{Entity, FillRule, Object, Symbol};
+use crate::s7_image::Rgb;
+use deflate::deflate_bytes_zlib;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+fn color_components(color: Rgb) -> String {
+    format!(
+        "{:.4} {:.4} {:.4}",
+        color.0 as f32 / 255.0,
+        color.1 as f32 / 255.0,
+        color.2 as f32 / 255.0
+    )
+}
+
+struct Page<'a> {
+    content: String,
+    patterns: Vec<String>,
+    images: Vec<Vec<u8>>,
+    symbols: &'a [Symbol],
+    height: u16,
+}
+
+// Points are pixel coordinates, offset them to the pixel centers
+fn path_operators(points: &[(u16, u16)]) -> String {
+    let mut operators = String::new();
+
+    for (index, (x, y)) in points.iter().enumerate() {
+        let operator = if index == 0 { "m" } else { "l" };
+        operators.push_str(&format!("{}.5 {}.5 {}\n", x, y, operator));
+    }
+    operators.push_str("h\n");
+
+    operators
+}
+
+fn write_object(page: &mut Page, object: &Object) {
+    let content = &mut page.content;
+
+    match object {
+        Object::Path(path) => {
+            if path.points.is_empty() {
+                return;
+            }
+
+            let color = color_components(path.color);
+
+            // A path covering a single pixel has no area, so fill the pixel itself
+            if path.points.iter().all(|pt| *pt == path.points[0]) {
+                let (x, y) = path.points[0];
+                content.push_str(&format!("{} rg {} {} 1 1 re f\n", color, x, y));
+                return;
+            }
+
+            content.push_str(&format!("{} rg {} RG\n", color, color));
+            content.push_str(&path_operators(&path.points));
+
+            // Fill and stroke, the stroke covers the boundary pixels
+            content.push_str("B\n");
+        }
+        Object::CompoundPath(path) => {
+            let rings: Vec<&Vec<(u16, u16)>> =
+                path.rings.iter().filter(|ring| !ring.is_empty()).collect();
+
+            if rings.is_empty() {
+                return;
+            }
+
+            let color = color_components(path.color);
+            content.push_str(&format!("{} rg {} RG\n", color, color));
+
+            for ring in rings {
+                content.push_str(&path_operators(ring));
+            }
+
+            // Fill with the fill rule and stroke
+            content.push_str(match path.fill_rule {
+                FillRule::NonZero => "B\n",
+                FillRule::EvenOdd => "B*\n",
+            });
+        }
+        Object::Rect(rect) => content.push_str(&format!(
+            "{} rg {} {} {} {} re f\n",
+            color_components(rect.color),
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height
+        )),
+        // Images can't be empty, and empty blocks don't draw anything anyway
+        Object::PixelBlock(block) if block.width == 0 || block.height == 0 => (),
+        Object::PixelBlock(block) => {
+            let pixels = deflate_bytes_zlib(&block.pixels);
+
+            let mut image = format!(
+                "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>\nstream\n",
+                block.width,
+                block.height,
+                pixels.len()
+            )
+            .into_bytes();
+            image.extend_from_slice(&pixels);
+            image.extend_from_slice(b"\nendstream");
+            page.images.push(image);
+
+            // Map the unit square to the block, image rows go from top to bottom
+            content.push_str(&format!(
+                "q {} 0 0 -{} {} {} cm /I{} Do Q\n",
+                block.width,
+                block.height,
+                block.x,
+                block.y as u32 + block.height as u32,
+                page.images.len() - 1
+            ));
+        }
+        Object::LinearGradient(gradient) => {
+            if gradient.points.is_empty() {
+                return;
+            }
+
+            if gradient.points.iter().all(|pt| *pt == gradient.points[0]) {
+                let (x, y) = gradient.points[0];
+                let color = color_components(gradient.start_color);
+                content.push_str(&format!("{} rg {} {} 1 1 re f\n", color, x, y));
+                return;
+            }
+
+            let (start_x, start_y) = gradient.start;
+            let (end_x, end_y) = gradient.end;
+
+            // Patterns live in the default page space, so they need the same flip as the content
+            page.patterns.push(format!(
+                "<< /PatternType 2 /Matrix [1 0 0 -1 0 {}] /Shading << /ShadingType 2 /ColorSpace /DeviceRGB /Coords [{}.5 {}.5 {}.5 {}.5] /Extend [true true] /Function << /FunctionType 2 /Domain [0 1] /C0 [{}] /C1 [{}] /N 1 >> >> >>",
+                page.height,
+                start_x,
+                start_y,
+                end_x,
+                end_y,
+                color_components(gradient.start_color),
+                color_components(gradient.end_color)
+            ));
+
+            let name = format!("P{}", page.patterns.len() - 1);
+            content.push_str(&format!(
+                "/Pattern cs /{} scn /Pattern CS /{} SCN\n",
+                name, name
+            ));
+            content.push_str(&path_operators(&gradient.points));
+            content.push_str("B\n");
+        }
+        // Copies are written as the pixel blocks they decode to
+        Object::Copy(_) => (),
+        Object::Symbol(placement) => {
+            let symbol = match page.symbols.get(placement.symbol as usize) {
+                Some(symbol) => symbol,
+                None => return,
+            };
+
+            // Stencil masks are painted in the fill color, like image rows from top to bottom
+            content.push_str(&format!(
+                "q {} rg {} 0 0 -{} {} {} cm /Y{} Do Q\n",
+                color_components(placement.color),
+                symbol.width,
+                symbol.height,
+                placement.x,
+                placement.y as u32 + symbol.height as u32,
+                placement.symbol
+            ));
+        }
+        Object::Runs(runs) => {
+            for run in &runs.runs {
+                content.push_str(&format!(
+                    "{} rg {} {} {} 1 re f\n",
+                    color_components(run.color),
+                    run.x_start,
+                    run.y,
+                    run.length
+                ));
+            }
+        }
+    }
+}
+
+// Stencil mask with one bit per pixel, rows are padded to whole bytes
+fn symbol_object(symbol: &Symbol) -> String {
+    // Images can't be empty, empty symbols become a single unset pixel
+    let width = symbol.width.max(1) as usize;
+    let height = symbol.height.max(1) as usize;
+
+    let mut hex_rows = String::new();
+    for y in 0..height {
+        let mut row_bytes = vec![0u8; width.div_ceil(8)];
+
+        for x in 0..width {
+            let is_set = x < symbol.width as usize
+                && symbol.mask.get(x + y * symbol.width as usize) == Some(&true);
+
+            if is_set {
+                row_bytes[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+
+        for byte in row_bytes {
+            hex_rows.push_str(&format!("{:02x}", byte));
+        }
+    }
+    hex_rows.push('>');
+
+    format!(
+        "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ImageMask true /BitsPerComponent 1 /Decode [1 0] /Filter /ASCIIHexDecode /Length {} >>\nstream\n{}\nendstream",
+        width,
+        height,
+        hex_rows.len(),
+        hex_rows
+    )
+}
+
+fn page(entity: &Entity) -> Page<'_> {
+    let width = entity.header_chunk.width;
+    let height = entity.header_chunk.height;
+
+    let mut page = Page {
+        content: String::new(),
+        patterns: Vec::new(),
+        images: Vec::new(),
+        symbols: entity.symbols(),
+        height,
+    };
+    let content = &mut page.content;
+
+    // Flip the y axis so that the origin is at the top left like in the image
+    content.push_str(&format!("1 0 0 -1 0 {} cm\n", height));
+    content.push_str("1 w 1 J 1 j\n");
+
+    // Decoding starts from an image filled with the background color
+    content.push_str(&format!(
+        "{} rg 0 0 {} {} re f\n",
+        color_components(entity.header_chunk.background()),
+        width,
+        height
+    ));
+
+    let mut copied_pixel_blocks = copied_pixel_blocks(entity).into_iter();
+
+    for data_chunk in &entity.data_chunks {
+        // Objects only draw inside the extent of their chunk
+        if let Some(extent) = data_chunk.extent {
+            page.content.push_str(&format!(
+                "q {} {} {} {} re W n\n",
+                extent.x, extent.y, extent.width, extent.height
+            ));
+        }
+
+        for object in &data_chunk.objects {
+            match object {
+                Object::Copy(_) => {
+                    if let Some(block) = copied_pixel_blocks.next().flatten() {
+                        write_object(&mut page, &Object::PixelBlock(block));
+                    }
+                }
+                _ => write_object(&mut page, object),
+            }
+        }
+
+        if data_chunk.extent.is_some() {
+            page.content.push_str("Q\n");
+        }
+    }
+
+    page
+}
+
+pub fn write_to<W: Write>(writer: &mut W, entity: &Entity) -> io::Result<()> {
+    let width = entity.header_chunk.width;
+    let height = entity.header_chunk.height;
+    let page = page(entity);
+
+    // Patterns are stored right after the content stream, followed by the
+    // symbols and the images of the pixel blocks
+    let pattern_resources: String = (0..page.patterns.len())
+        .map(|index| format!("/P{} {} 0 R ", index, index + 5))
+        .collect();
+    let symbols_start = 5 + page.patterns.len();
+    let symbol_resources: String = (0..page.symbols.len())
+        .map(|index| format!("/Y{} {} 0 R ", index, index + symbols_start))
+        .collect();
+    let images_start = symbols_start + page.symbols.len();
+    let image_resources: String = (0..page.images.len())
+        .map(|index| format!("/I{} {} 0 R ", index, index + images_start))
+        .collect();
+
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Pattern << {}>> /XObject << {}{}>> >> /Contents 4 0 R >>",
+            width, height, pattern_resources, symbol_resources, image_resources
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            page.content.len(),
+            page.content
+        ),
+    ];
+    objects.extend(page.patterns);
+    objects.extend(page.symbols.iter().map(symbol_object));
+
+    // Images hold binary data, so the objects are written as bytes
+    let objects: Vec<Vec<u8>> = objects
+        .into_iter()
+        .map(String::into_bytes)
+        .chain(page.images)
+        .collect();
+
+    let mut data: Vec<u8> = Vec::new();
+    let mut offsets = Vec::new();
+
+    // The comment with high bytes marks the file as binary
+    data.extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(data.len());
+        data.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        data.extend_from_slice(object);
+        data.extend_from_slice(b"\nendobj\n");
+    }
+
+    // Cross-reference table, every entry has to be exactly 20 bytes long
+    let xref_offset = data.len();
+    data.extend_from_slice(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+    data.extend_from_slice(b"0000000000 65535 f \n");
+    for offset in offsets {
+        data.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+
+    data.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+
+    writer.write_all(&data)
+}
+
+pub fn write(out_path: &str, entity: &Entity) -> io::Result<()> {
+    let file = File::create(out_path)?;
+    let mut writer = BufWriter::new(file);
+
+    write_to(&mut writer, entity)?;
+    writer.flush()
+}
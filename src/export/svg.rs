@@ -0,0 +1,306 @@
+use super::copied_pixel_blocks;
+use crate::entity::{Entity, FillRule, Object, Symbol};
+use crate::s7_image::Rgb;
+use image::codecs::png::PngEncoder;
+use image::ColorType;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+fn hex_color(color: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+
+    for bytes in data.chunks(3) {
+        let group = bytes.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - i * 8)
+        });
+
+        for i in 0..4 {
+            if i <= bytes.len() {
+                let index = (group >> (18 - i * 6)) & 0x3f;
+                encoded.push(ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn path_data(points: &[(u16, u16)]) -> String {
+    // A path covering a single pixel has no area, so draw the pixel itself
+    if points.iter().all(|pt| *pt == points[0]) {
+        let (x, y) = points[0];
+        return format!("M{} {}h1v1h-1Z", x, y);
+    }
+
+    // Points are pixel coordinates, offset them to the pixel centers
+    let mut data = String::new();
+    for (index, (x, y)) in points.iter().enumerate() {
+        let command = if index == 0 { 'M' } else { 'L' };
+        data.push_str(&format!("{}{}.5 {}.5", command, x, y));
+    }
+    data.push('Z');
+
+    data
+}
+
+// Rows of the mask as pixel squares, relative to the placement
+fn symbol_path_data(symbol: &Symbol) -> String {
+    let mut data = String::new();
+
+    for (y, row) in symbol.mask.chunks(symbol.width.max(1) as usize).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            if !row[x] {
+                x += 1;
+                continue;
+            }
+
+            let length = row[x..].iter().take_while(|is_set| **is_set).count();
+            data.push_str(&format!("M{} {}h{}v1h-{}Z", x, y, length, length));
+            x += length;
+        }
+    }
+
+    data
+}
+
+fn write_object<W: Write>(writer: &mut W, object: &Object, index: usize) -> io::Result<()> {
+    match object {
+        Object::Path(path) => {
+            if path.points.is_empty() {
+                return Ok(());
+            }
+
+            let color = hex_color(path.color);
+
+            // The stroke covers the boundary pixels, which the decoder fills as well
+            writeln!(
+                writer,
+                "<path d=\"{}\" fill=\"{}\" stroke=\"{}\"/>",
+                path_data(&path.points),
+                color,
+                color
+            )
+        }
+        Object::CompoundPath(path) => {
+            let data: String = path
+                .rings
+                .iter()
+                .filter(|ring| !ring.is_empty())
+                .map(|ring| path_data(ring))
+                .collect();
+
+            if data.is_empty() {
+                return Ok(());
+            }
+
+            let fill_rule = match path.fill_rule {
+                FillRule::NonZero => "nonzero",
+                FillRule::EvenOdd => "evenodd",
+            };
+            let color = hex_color(path.color);
+
+            writeln!(
+                writer,
+                "<path d=\"{}\" fill-rule=\"{}\" fill=\"{}\" stroke=\"{}\"/>",
+                data, fill_rule, color, color
+            )
+        }
+        Object::Rect(rect) => writeln!(
+            writer,
+            "<path d=\"M{} {}h{}v{}h-{}Z\" fill=\"{}\"/>",
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            rect.width,
+            hex_color(rect.color)
+        ),
+        // Images can't be empty, and empty blocks don't draw anything anyway
+        Object::PixelBlock(block) if block.width == 0 || block.height == 0 => Ok(()),
+        Object::PixelBlock(block) => {
+            let mut png = Vec::new();
+            PngEncoder::new(&mut png)
+                .encode(
+                    &block.pixels,
+                    block.width as u32,
+                    block.height as u32,
+                    ColorType::Rgb8,
+                )
+                .map_err(io::Error::other)?;
+
+            writeln!(
+                writer,
+                "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" image-rendering=\"pixelated\" href=\"data:image/png;base64,{}\"/>",
+                block.x,
+                block.y,
+                block.width,
+                block.height,
+                base64(&png)
+            )
+        }
+        Object::LinearGradient(gradient) => {
+            if gradient.points.is_empty() {
+                return Ok(());
+            }
+
+            let (start_x, start_y) = gradient.start;
+            let (end_x, end_y) = gradient.end;
+
+            // Gradient coordinates are offset to the pixel centers like the points
+            writeln!(
+                writer,
+                "<linearGradient id=\"g{}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}.5\" y1=\"{}.5\" x2=\"{}.5\" y2=\"{}.5\"><stop offset=\"0\" stop-color=\"{}\"/><stop offset=\"1\" stop-color=\"{}\"/></linearGradient>",
+                index,
+                start_x,
+                start_y,
+                end_x,
+                end_y,
+                hex_color(gradient.start_color),
+                hex_color(gradient.end_color)
+            )?;
+
+            writeln!(
+                writer,
+                "<path d=\"{}\" fill=\"url(#g{})\" stroke=\"url(#g{})\"/>",
+                path_data(&gradient.points),
+                index,
+                index
+            )
+        }
+        // Copies are written as the pixel blocks they decode to
+        Object::Copy(_) => Ok(()),
+        Object::Symbol(placement) => writeln!(
+            writer,
+            "<use href=\"#y{}\" x=\"{}\" y=\"{}\" fill=\"{}\"/>",
+            placement.symbol,
+            placement.x,
+            placement.y,
+            hex_color(placement.color)
+        ),
+        Object::Runs(runs) => {
+            writeln!(writer, "<g>")?;
+
+            // Consecutive runs of the same color share a path
+            let mut index = 0;
+            while index < runs.runs.len() {
+                let color = runs.runs[index].color;
+                let mut data = String::new();
+
+                while index < runs.runs.len() && runs.runs[index].color == color {
+                    let run = &runs.runs[index];
+                    data.push_str(&format!(
+                        "M{} {}h{}v1h-{}Z",
+                        run.x_start, run.y, run.length, run.length
+                    ));
+                    index += 1;
+                }
+
+                writeln!(
+                    writer,
+                    "<path d=\"{}\" fill=\"{}\"/>",
+                    data,
+                    hex_color(color)
+                )?;
+            }
+
+            writeln!(writer, "</g>")
+        }
+    }
+}
+
+pub fn write_to<W: Write>(writer: &mut W, entity: &Entity) -> io::Result<()> {
+    let width = entity.header_chunk.width;
+    let height = entity.header_chunk.height;
+
+    writeln!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+        width, height
+    )?;
+
+    // Decoding starts from an image filled with the background color
+    writeln!(
+        writer,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        width,
+        height,
+        hex_color(entity.header_chunk.background())
+    )?;
+
+    // Symbols are placed with the color of the placement
+    if !entity.symbols().is_empty() {
+        writeln!(writer, "<defs>")?;
+
+        for (index, symbol) in entity.symbols().iter().enumerate() {
+            writeln!(
+                writer,
+                "<path id=\"y{}\" d=\"{}\"/>",
+                index,
+                symbol_path_data(symbol)
+            )?;
+        }
+
+        writeln!(writer, "</defs>")?;
+    }
+
+    writeln!(writer, "<g stroke-width=\"1\" stroke-linejoin=\"round\">")?;
+
+    let mut copied_pixel_blocks = copied_pixel_blocks(entity).into_iter();
+    let mut index = 0;
+
+    for (chunk_index, data_chunk) in entity.data_chunks.iter().enumerate() {
+        // Objects only draw inside the extent of their chunk
+        if let Some(extent) = data_chunk.extent {
+            writeln!(
+                writer,
+                "<clipPath id=\"c{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></clipPath>",
+                chunk_index, extent.x, extent.y, extent.width, extent.height
+            )?;
+            writeln!(writer, "<g clip-path=\"url(#c{})\">", chunk_index)?;
+        }
+
+        for object in &data_chunk.objects {
+            match object {
+                Object::Copy(_) => {
+                    if let Some(block) = copied_pixel_blocks.next().flatten() {
+                        write_object(writer, &Object::PixelBlock(block), index)?;
+                    }
+                }
+                // Placements of missing symbols don't draw anything
+                Object::Symbol(placement)
+                    if entity.symbols().get(placement.symbol as usize).is_none() => {}
+                _ => write_object(writer, object, index)?,
+            }
+
+            index += 1;
+        }
+
+        if data_chunk.extent.is_some() {
+            writeln!(writer, "</g>")?;
+        }
+    }
+
+    writeln!(writer, "</g>")?;
+    writeln!(writer, "</svg>")?;
+
+    Ok(())
+}
+
+pub fn write(out_path: &str, entity: &Entity) -> io::Result<()> {
+    let file = File::create(out_path)?;
+    let mut writer = BufWriter::new(file);
+
+    write_to(&mut writer, entity)?;
+    writer.flush()
+}
@@ -0,0 +1,237 @@
+use crate::algorithm::decode::{decode_into, PixelFormat};
+use crate::algorithm::encode::encode;
+use crate::s7_image::Image;
+use crate::serialization::parse_error::ParseError;
+use crate::serialization::read::{read_from, read_header_from};
+use crate::serialization::write::write_to;
+use image::RgbImage;
+use std::io::Cursor;
+use std::panic;
+use std::ptr;
+use std::slice;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum S7Error {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    Parse = 3,
+    Panic = 4,
+    Limit = 5,
+    BufferSize = 6,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum S7PixelFormat {
+    Rgb = 0,
+    Rgba = 1,
+}
+
+impl S7PixelFormat {
+    // Formats are passed as integers, since C can pass any value for an enum
+    fn from_raw(format: u32) -> Option<Self> {
+        match format {
+            0 => Some(Self::Rgb),
+            1 => Some(Self::Rgba),
+            _ => None,
+        }
+    }
+
+    fn pixel_format(self) -> PixelFormat {
+        match self {
+            Self::Rgb => PixelFormat::Rgb,
+            Self::Rgba => PixelFormat::Rgba,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct S7Buffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl S7Buffer {
+    fn empty() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(data: Vec<u8>) -> Self {
+        let len = data.len();
+        let data = Box::into_raw(data.into_boxed_slice()) as *mut u8;
+
+        Self { data, len }
+    }
+}
+
+// Panics must not unwind across the FFI boundary
+fn catch_panic<F: FnOnce() -> S7Error + panic::UnwindSafe>(f: F) -> S7Error {
+    panic::catch_unwind(f).unwrap_or(S7Error::Panic)
+}
+
+fn parse_error(err: ParseError) -> S7Error {
+    log::debug!("{}", err);
+
+    if err.exceeds_limit() {
+        S7Error::Limit
+    } else {
+        S7Error::Parse
+    }
+}
+
+/// # Safety
+///
+/// `pixels` must point to `len` readable bytes and `out` must point to a writable `S7Buffer`.
+/// The buffer written to `out` has to be released with `s7_buffer_free`.
+#[no_mangle]
+pub unsafe extern "C" fn s7_encode(
+    pixels: *const u8,
+    len: usize,
+    width: u32,
+    height: u32,
+    format: u32,
+    out: *mut S7Buffer,
+) -> S7Error {
+    if pixels.is_null() || out.is_null() {
+        return S7Error::NullPointer;
+    }
+
+    *out = S7Buffer::empty();
+
+    let format = match S7PixelFormat::from_raw(format) {
+        Some(format) => format,
+        None => return S7Error::InvalidArgument,
+    };
+
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return S7Error::InvalidArgument;
+    }
+
+    if len != width as usize * height as usize * format.pixel_format().channels() {
+        return S7Error::BufferSize;
+    }
+
+    let pixels = slice::from_raw_parts(pixels, len);
+
+    catch_panic(|| {
+        let rgb_buf = match format {
+            S7PixelFormat::Rgb => pixels.to_vec(),
+            S7PixelFormat::Rgba => pixels
+                .chunks_exact(4)
+                .flat_map(|rgba| rgba[..3].iter().copied())
+                .collect(),
+        };
+
+        let rgb_image = RgbImage::from_raw(width, height, rgb_buf).unwrap();
+        let entity = encode(Image::new(rgb_image));
+
+        let mut cursor = Cursor::new(Vec::new());
+        write_to(&mut cursor, entity);
+
+        *out = S7Buffer::from_vec(cursor.into_inner());
+
+        S7Error::Ok
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, `out` must point to a writable `S7Buffer`
+/// and `width` and `height` must be writable or null.
+/// The buffer written to `out` has to be released with `s7_buffer_free`.
+#[no_mangle]
+pub unsafe extern "C" fn s7_decode(
+    data: *const u8,
+    len: usize,
+    format: u32,
+    out: *mut S7Buffer,
+    width: *mut u32,
+    height: *mut u32,
+) -> S7Error {
+    if data.is_null() || out.is_null() {
+        return S7Error::NullPointer;
+    }
+
+    *out = S7Buffer::empty();
+
+    let format = match S7PixelFormat::from_raw(format) {
+        Some(format) => format.pixel_format(),
+        None => return S7Error::InvalidArgument,
+    };
+
+    let data = slice::from_raw_parts(data, len);
+
+    catch_panic(|| {
+        let entity = match read_from(&mut Cursor::new(data)) {
+            Ok(entity) => entity,
+            Err(err) => return parse_error(err),
+        };
+
+        let image_width = entity.header_chunk.width as u32;
+        let image_height = entity.header_chunk.height as u32;
+
+        let row_length = image_width as usize * format.channels();
+        let mut pixels = vec![0; row_length * image_height as usize];
+        if decode_into(&entity, &mut pixels, row_length, format).is_err() {
+            return S7Error::BufferSize;
+        }
+
+        if !width.is_null() {
+            *width = image_width;
+        }
+        if !height.is_null() {
+            *height = image_height;
+        }
+
+        *out = S7Buffer::from_vec(pixels);
+
+        S7Error::Ok
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `width` and `height` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn s7_dimensions(
+    data: *const u8,
+    len: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> S7Error {
+    if data.is_null() || width.is_null() || height.is_null() {
+        return S7Error::NullPointer;
+    }
+
+    let data = slice::from_raw_parts(data, len);
+
+    catch_panic(|| match read_header_from(&mut Cursor::new(data)) {
+        Ok(header_chunk) => {
+            *width = header_chunk.width as u32;
+            *height = header_chunk.height as u32;
+
+            S7Error::Ok
+        }
+        Err(err) => parse_error(err),
+    })
+}
+
+/// # Safety
+///
+/// `buffer` must be null or point to an `S7Buffer` filled by this library.
+#[no_mangle]
+pub unsafe extern "C" fn s7_buffer_free(buffer: *mut S7Buffer) {
+    if buffer.is_null() || (*buffer).data.is_null() {
+        return;
+    }
+
+    let data = slice::from_raw_parts_mut((*buffer).data, (*buffer).len);
+    drop(Box::from_raw(data as *mut [u8]));
+
+    *buffer = S7Buffer::empty();
+}
@@ -1,6 +1,11 @@
 pub mod algorithm;
+pub mod codec;
 pub mod entity;
+pub mod export;
+pub mod ffi;
+pub mod limits;
 pub mod read_png;
 pub mod s7_image;
 pub mod serialization;
 pub mod utils;
+pub mod write_png;
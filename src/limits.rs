@@ -0,0 +1,374 @@
+use super::entity::{Entity, Object};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct LimitError {
+    message: String,
+}
+
+impl LimitError {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LimitError: {}", self.message)
+    }
+}
+
+impl Error for LimitError {}
+
+// Limits on the resources untrusted files can make the reader and decoder
+// allocate, sizes in files are only checked against them before allocating
+#[derive(Clone, Copy, Debug)]
+pub struct ReadLimits {
+    pub max_chunk_size: u32,
+    pub max_pixels: u64,
+    pub max_objects: usize,
+    pub max_points: usize,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_chunk_size: 256 * 1024 * 1024,
+            max_pixels: 16384 * 16384,
+            max_objects: 1 << 24,
+            max_points: 1 << 24,
+        }
+    }
+}
+
+impl ReadLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_chunk_size: u32::MAX,
+            max_pixels: u64::MAX,
+            max_objects: usize::MAX,
+            max_points: usize::MAX,
+        }
+    }
+
+    pub fn check_chunk_size(&self, size: u32) -> Result<(), LimitError> {
+        if size > self.max_chunk_size {
+            return Err(LimitError::new(&format!(
+                "Chunk size {} exceeds the limit of {}",
+                size, self.max_chunk_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_dimensions(&self, width: u16, height: u16) -> Result<(), LimitError> {
+        let pixels = width as u64 * height as u64;
+
+        if pixels > self.max_pixels {
+            return Err(LimitError::new(&format!(
+                "Image of {}x{} pixels exceeds the limit of {} pixels",
+                width, height, self.max_pixels
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_object_count(&self, count: usize) -> Result<(), LimitError> {
+        if count > self.max_objects {
+            return Err(LimitError::new(&format!(
+                "Object count {} exceeds the limit of {}",
+                count, self.max_objects
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_points(&self, points: usize) -> Result<(), LimitError> {
+        if points > self.max_points {
+            return Err(LimitError::new(&format!(
+                "Path with {} points exceeds the limit of {}",
+                points, self.max_points
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_runs(&self, runs: usize) -> Result<(), LimitError> {
+        if runs > self.max_points {
+            return Err(LimitError::new(&format!(
+                "Object with {} runs exceeds the limit of {}",
+                runs, self.max_points
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Symbols count towards the objects, and their masks towards the pixels as
+    // they are expanded to one value per pixel
+    pub fn check_symbols(&self, count: usize, pixels: u64) -> Result<(), LimitError> {
+        if count > self.max_objects {
+            return Err(LimitError::new(&format!(
+                "Symbol count {} exceeds the limit of {}",
+                count, self.max_objects
+            )));
+        }
+
+        if pixels > self.max_pixels {
+            return Err(LimitError::new(&format!(
+                "Symbols of {} pixels exceed the limit of {} pixels",
+                pixels, self.max_pixels
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Pixel blocks are stored compressed, so all of them together are checked
+    // against the pixels they expand into. The encoder only writes disjoint
+    // blocks, which can't hold more pixels than the image either.
+    pub fn check_block_pixels(&self, pixels: u64, image_pixels: u64) -> Result<(), LimitError> {
+        let max_pixels = self.max_pixels.min(image_pixels);
+
+        if pixels > max_pixels {
+            return Err(LimitError::new(&format!(
+                "Pixel blocks of {} pixels exceed the limit of {} pixels",
+                pixels, max_pixels
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_object(&self, object: &Object) -> Result<(), LimitError> {
+        match object {
+            Object::Path(path) => self.check_points(path.points.len()),
+            Object::LinearGradient(gradient) => self.check_points(gradient.points.len()),
+            // Rings count as a point each, so that empty rings can't pile up
+            Object::CompoundPath(path) => {
+                self.check_points(path.rings.iter().map(|ring| ring.len() + 1).sum())
+            }
+            Object::PixelBlock(block) => self.check_dimensions(block.width, block.height),
+            Object::Runs(runs) => self.check_runs(runs.runs.len()),
+            Object::Copy(copy) => self.check_dimensions(copy.src.width, copy.src.height),
+            Object::Rect(_) | Object::Symbol(_) => Ok(()),
+        }
+    }
+
+    pub fn check_entity(&self, entity: &Entity) -> Result<(), LimitError> {
+        let header_chunk = &entity.header_chunk;
+        self.check_dimensions(header_chunk.width, header_chunk.height)?;
+
+        let symbols = entity.symbols();
+        let symbol_pixels = symbols
+            .iter()
+            .map(|symbol| symbol.width as u64 * symbol.height as u64)
+            .sum();
+        self.check_symbols(symbols.len(), symbol_pixels)?;
+
+        let objects = entity
+            .data_chunks
+            .iter()
+            .flat_map(|data_chunk| data_chunk.objects.iter());
+
+        let mut count = 0;
+        let mut block_pixels = 0;
+        for object in objects {
+            self.check_object(object)?;
+            count += 1;
+
+            if let Object::PixelBlock(block) = object {
+                block_pixels += block.width as u64 * block.height as u64;
+                self.check_block_pixels(
+                    block_pixels,
+                    header_chunk.width as u64 * header_chunk.height as u64,
+                )?;
+            }
+        }
+
+        self.check_object_count(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadLimits;
+    use crate::entity::{
+        CompoundPathObject, DataChunk, Entity, FillRule, Object, PixelBlockObject, Symbol,
+        SymbolChunk,
+    };
+    use crate::s7_image::Rgb;
+    use crate::serialization::read::read_from_with_limits;
+    use crate::serialization::write::tests::entity_with_every_object;
+    use crate::serialization::write::write_stream;
+    use byteorder::{BigEndian, ByteOrder};
+    use std::io::Cursor;
+
+    fn write(entity: Entity) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_stream(&mut data, entity).unwrap();
+
+        data
+    }
+
+    // Size of the largest chunk's data, the chunks follow the zero byte ending
+    // the version
+    fn max_chunk_size(data: &[u8]) -> u32 {
+        let mut pos = data.iter().position(|byte| *byte == 0).unwrap() + 1;
+        let mut max_size = 0;
+
+        while pos < data.len() {
+            let size = BigEndian::read_u32(&data[pos..]);
+            max_size = max_size.max(size - 8);
+            pos += size as usize;
+        }
+
+        max_size
+    }
+
+    fn is_accepted(data: &[u8], limits: ReadLimits) -> bool {
+        read_from_with_limits(&mut Cursor::new(data), limits).is_ok()
+    }
+
+    // The limits are just large enough for the entity, lowering any of them
+    // rejects it
+    #[test]
+    fn tight_limits() {
+        let data = write(entity_with_every_object());
+        let limits = ReadLimits {
+            max_chunk_size: max_chunk_size(&data),
+            // Size of the image
+            max_pixels: 64 * 32,
+            max_objects: 9,
+            // Points of the compound path and one for each of its rings
+            max_points: 9,
+        };
+
+        assert!(is_accepted(&data, limits));
+        assert!(limits.check_entity(&entity_with_every_object()).is_ok());
+
+        let lowered_limits = [
+            ReadLimits {
+                max_chunk_size: limits.max_chunk_size - 1,
+                ..limits
+            },
+            ReadLimits {
+                max_pixels: limits.max_pixels - 1,
+                ..limits
+            },
+            ReadLimits {
+                max_objects: limits.max_objects - 1,
+                ..limits
+            },
+            ReadLimits {
+                max_points: limits.max_points - 1,
+                ..limits
+            },
+        ];
+
+        for lowered_limits in lowered_limits {
+            assert!(!is_accepted(&data, lowered_limits), "{:?}", lowered_limits);
+        }
+
+        // Chunk sizes aren't known once the file is parsed
+        for lowered_limits in &lowered_limits[1..] {
+            assert!(lowered_limits
+                .check_entity(&entity_with_every_object())
+                .is_err());
+        }
+    }
+
+    fn entity_with_objects(objects: Vec<Object>) -> Entity {
+        let mut entity = entity_with_every_object();
+        entity.header_chunk.width = 8;
+        entity.header_chunk.height = 8;
+        entity.symbol_chunk = None;
+        entity.data_chunks = vec![DataChunk {
+            extent: None,
+            objects,
+        }];
+
+        entity
+    }
+
+    fn block() -> Object {
+        Object::PixelBlock(PixelBlockObject {
+            x: 0,
+            y: 0,
+            width: 8,
+            height: 8,
+            pixels: vec![0; 8 * 8 * 3],
+        })
+    }
+
+    // Blocks compress well, so all of them together may only cover the image
+    #[test]
+    fn pixel_blocks_together() {
+        let limits = ReadLimits::default();
+
+        let data = write(entity_with_objects(vec![block()]));
+        assert!(is_accepted(&data, limits));
+
+        let data = write(entity_with_objects(vec![block(), block()]));
+        assert!(!is_accepted(&data, limits));
+        assert!(limits
+            .check_entity(&entity_with_objects(vec![block(), block()]))
+            .is_err());
+    }
+
+    // Rings are counted even when they are empty
+    #[test]
+    fn empty_rings() {
+        let limits = ReadLimits {
+            max_points: 9,
+            ..ReadLimits::default()
+        };
+        let path = |rings| {
+            Object::CompoundPath(CompoundPathObject {
+                color: Rgb(0, 0, 0),
+                fill_rule: FillRule::NonZero,
+                rings: vec![Vec::new(); rings],
+            })
+        };
+
+        assert!(is_accepted(
+            &write(entity_with_objects(vec![path(9)])),
+            limits
+        ));
+        assert!(!is_accepted(
+            &write(entity_with_objects(vec![path(10)])),
+            limits
+        ));
+        assert!(limits
+            .check_entity(&entity_with_objects(vec![path(10)]))
+            .is_err());
+    }
+
+    // Masks hold a value per pixel, even for symbols larger than the image
+    #[test]
+    fn symbol_pixels() {
+        let limits = ReadLimits {
+            max_pixels: 5000,
+            ..ReadLimits::default()
+        };
+        let mut entity = entity_with_objects(Vec::new());
+        entity.symbol_chunk = Some(SymbolChunk {
+            symbols: vec![Symbol {
+                width: 100,
+                height: 100,
+                mask: vec![true; 100 * 100],
+            }],
+        });
+
+        assert!(limits.check_entity(&entity).is_err());
+        assert!(!is_accepted(&write(entity), limits));
+    }
+}
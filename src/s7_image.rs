@@ -1,11 +1,14 @@
 use image::RgbImage;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
 use std::convert::From;
 
 pub type Coords = (u16, u16);
 
 const RGB_DIFFERENCE: u8 = 10;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct Rgb(pub u8, pub u8, pub u8);
 
 impl From<Rgb> for image::Rgb<u8> {
@@ -37,12 +40,20 @@ pub struct Image {
 
 impl Image {
     pub fn new(image: RgbImage) -> Self {
-        return Self {
+        Self {
             width: image.width() as u16,
             height: image.height() as u16,
             checked_pixels: vec![false; (image.width() * image.height()) as usize],
             buf: image,
-        };
+        }
+    }
+
+    // Copy of the part of the image starting at the coordinates
+    pub fn sub_image(&self, (x, y): Coords, (width, height): (u16, u16)) -> Self {
+        let buf =
+            image::imageops::crop_imm(&self.buf, x as u32, y as u32, width as u32, height as u32);
+
+        Self::new(buf.to_image())
     }
 
     fn get_pixel_index(&self, coords: Coords) -> usize {
@@ -56,6 +67,11 @@ impl Image {
         Rgb(r, g, b)
     }
 
+    pub fn set_pixel(&mut self, coords: Coords, color: Rgb) {
+        let (x, y) = coords;
+        self.buf.put_pixel(x as u32, y as u32, color.into());
+    }
+
     pub fn is_valid_coords(&self, coords: Coords) -> bool {
         let (x, y) = coords;
         x < self.width && y < self.height
@@ -79,7 +95,7 @@ impl Image {
         let a_avg = (a.0 as u16 + a.1 as u16 + a.2 as u16) / 3;
         let b_avg = (b.0 as u16 + b.1 as u16 + b.2 as u16) / 3;
 
-        let diff = (a_avg as i16 - b_avg as i16).abs() as u8;
+        let diff = (a_avg as i16 - b_avg as i16).unsigned_abs() as u8;
         diff <= RGB_DIFFERENCE
     }
 }
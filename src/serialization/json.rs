@@ -0,0 +1,43 @@
+use super::parse_error::ParseError;
+use crate::entity::Entity;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+// Attribute keys and chunk codes are written as four bytes, which keys in
+// hand-edited files don't necessarily have
+fn check_code(code: &str) -> Result<(), ParseError> {
+    if code.len() != 4 {
+        return Err(ParseError::new(&format!(
+            "Code '{}' has to be four bytes long",
+            code
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn read(path: &str) -> Result<Entity, ParseError> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    let entity: Entity = serde_json::from_reader(reader)?;
+
+    for attribute in &entity.header_chunk.other_attributes {
+        check_code(&attribute.key)?;
+    }
+    for chunk in &entity.other_chunks {
+        check_code(&chunk.code)?;
+    }
+
+    Ok(entity)
+}
+
+pub fn write(out_path: &str, entity: &Entity) {
+    let file = File::create(out_path).unwrap();
+    let mut writer = BufWriter::new(file);
+
+    serde_json::to_writer_pretty(&mut writer, entity).unwrap();
+    writer.write_all(b"\n").unwrap();
+    writer.flush().unwrap();
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "json")]
+pub mod json;
 pub mod parse_error;
 pub mod read;
+pub mod slice_read;
+pub mod varint;
 pub mod write;
@@ -1,4 +1,6 @@
+use crate::limits::LimitError;
 use std::convert::From;
+use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
 use std::io;
@@ -6,14 +8,21 @@ use std::io;
 #[derive(Debug)]
 pub struct ParseError {
     message: String,
+    exceeds_limit: bool,
 }
 
 impl ParseError {
     pub fn new(message: &str) -> Self {
         Self {
             message: message.to_owned(),
+            exceeds_limit: false,
         }
     }
+
+    // Whether the file was rejected by the read limits rather than malformed
+    pub fn exceeds_limit(&self) -> bool {
+        self.exceeds_limit
+    }
 }
 
 impl Display for ParseError {
@@ -22,10 +31,32 @@ impl Display for ParseError {
     }
 }
 
+impl Error for ParseError {}
+
+impl From<LimitError> for ParseError {
+    fn from(err: LimitError) -> Self {
+        Self {
+            message: format!("{}", err),
+            exceeds_limit: true,
+        }
+    }
+}
+
 impl From<io::Error> for ParseError {
     fn from(err: io::Error) -> Self {
         Self {
             message: format!("{}", err),
+            exceeds_limit: false,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for ParseError {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            message: format!("{}", err),
+            exceeds_limit: false,
         }
     }
 }
@@ -1,12 +1,21 @@
 use super::parse_error::ParseError;
-use crate::entity::{Chunk, DataChunk, Entity, HeaderAttibute, HeaderChunk, Object, PathObject};
+use super::varint::{read_varint, read_varint_u16, read_varint_u32};
+use crate::entity::{
+    Chunk, CompoundPathObject, CopyObject, DataChunk, Entity, FillRule, HeaderAttibute,
+    HeaderChunk, IndexEntry, LinearGradientObject, Object, PathObject, PixelBlockObject,
+    RectObject, Region, Run, RunsObject, Symbol, SymbolChunk, SymbolObject,
+    SUPPORTED_MAJOR_VERSIONS,
+};
+use crate::limits::ReadLimits;
+use crate::utils::{are_disjoint, regions_overlap};
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{BufReader, Cursor, SeekFrom};
+use std::io::{self, BufReader, Cursor, SeekFrom};
 use std::time::{Duration, UNIX_EPOCH};
 
-fn read_expect(reader: &mut BufReader<File>, expected: &Vec<u8>) -> Result<(), ParseError> {
+fn read_expect<R: Read>(reader: &mut R, expected: &Vec<u8>) -> Result<(), ParseError> {
     let mut buf: Vec<u8> = vec![0; expected.len()];
     reader.read_exact(&mut buf)?;
 
@@ -17,37 +26,91 @@ fn read_expect(reader: &mut BufReader<File>, expected: &Vec<u8>) -> Result<(), P
     Ok(())
 }
 
-fn read_signature(reader: &mut BufReader<File>) -> Result<String, ParseError> {
-    read_expect(reader, &vec![0x0d, 'S' as u8, '7' as u8, 0x0d])?;
+fn read_signature<R: BufRead>(reader: &mut R) -> Result<String, ParseError> {
+    read_expect(reader, &vec![0x0d, b'S', b'7', 0x0d])?;
 
     let mut version_buf: Vec<u8> = Vec::new();
     reader.read_until(0, &mut version_buf)?;
 
-    let mut version = String::from_utf8(version_buf).unwrap();
-    version.pop(); // Remove trailing zero byte
+    // Remove trailing zero byte
+    if version_buf.pop() != Some(0) {
+        return Err(ParseError::new("Version has to end with a zero byte"));
+    }
+
+    let version = String::from_utf8(version_buf)
+        .map_err(|_| ParseError::new("Version is not valid UTF-8"))?;
+    check_version(&version)?;
 
     Ok(version)
 }
 
-fn read_chunk(reader: &mut BufReader<File>) -> Result<Chunk, ParseError> {
-    let size = reader.read_u32::<BigEndian>()?;
+// Newer major versions may contain objects this reader would misinterpret
+pub(super) fn check_version(version: &str) -> Result<(), ParseError> {
+    let major = version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse().ok());
+
+    match major {
+        Some(major) if SUPPORTED_MAJOR_VERSIONS.contains(&major) => Ok(()),
+        _ => Err(ParseError::new(&format!("Unsupported version {}", version))),
+    }
+}
 
+fn read_code<R: Read>(reader: &mut R) -> Result<String, ParseError> {
     let mut code_bytes = [0u8; 4];
     reader.read_exact(&mut code_bytes)?;
-    let code = String::from_utf8(code_bytes.into()).unwrap();
 
-    let mut data = vec![0u8; (size - 8) as usize];
-    reader.read_exact(&mut data)?;
+    String::from_utf8(code_bytes.into()).map_err(|_| ParseError::new("Code is not valid UTF-8"))
+}
+
+// Reads the size and the code of a chunk, returning the code and the size of
+// the data following them
+fn read_chunk_header<R: Read>(
+    reader: &mut R,
+    limits: &ReadLimits,
+) -> Result<(String, u32), ParseError> {
+    let size = reader.read_u32::<BigEndian>()?;
+    if size < 8 {
+        return Err(ParseError::new("Chunk size is smaller than its header"));
+    }
+    limits.check_chunk_size(size - 8)?;
+
+    let code = read_code(reader)?;
+
+    Ok((code, size - 8))
+}
+
+fn read_chunk_data<R: Read>(reader: &mut R, code: String, size: u32) -> Result<Chunk, ParseError> {
+    // The data is read as it comes instead of allocating the size up front,
+    // which truncated files could make arbitrarily large
+    let mut data = Vec::new();
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() != size as usize {
+        return Err(ParseError::new("Unexpected end of chunk"));
+    }
 
     Ok(Chunk { code, data })
 }
 
+fn read_chunk<R: Read>(reader: &mut R, limits: &ReadLimits) -> Result<Chunk, ParseError> {
+    let (code, size) = read_chunk_header(reader, limits)?;
+
+    read_chunk_data(reader, code, size)
+}
+
 fn read_header_attribute(chunk_reader: &mut Cursor<Vec<u8>>) -> Result<HeaderAttibute, ParseError> {
     let size = chunk_reader.read_u32::<BigEndian>()?;
+    if size < 8 {
+        return Err(ParseError::new("Attribute size is smaller than its header"));
+    }
 
-    let mut code_bytes = [0u8; 4];
-    chunk_reader.read_exact(&mut code_bytes)?;
-    let code = String::from_utf8(code_bytes.into()).unwrap();
+    let remaining = chunk_reader.get_ref().len() as u64 - chunk_reader.position();
+    if size as u64 > remaining + 4 {
+        return Err(ParseError::new("Attribute is larger than its chunk"));
+    }
+
+    let code = read_code(chunk_reader)?;
 
     let mut data = vec![0u8; (size - 8) as usize];
     chunk_reader.read_exact(&mut data)?;
@@ -58,7 +121,7 @@ fn read_header_attribute(chunk_reader: &mut Cursor<Vec<u8>>) -> Result<HeaderAtt
     })
 }
 
-fn parse_header_chunk(chunk: Chunk) -> Result<HeaderChunk, ParseError> {
+pub(super) fn parse_header_chunk(chunk: Chunk) -> Result<HeaderChunk, ParseError> {
     if chunk.code != "HEAD" {
         return Err(ParseError::new("Expected header chunk"));
     }
@@ -70,20 +133,45 @@ fn parse_header_chunk(chunk: Chunk) -> Result<HeaderChunk, ParseError> {
     let mut creation_date = None;
     let mut width = 0;
     let mut height = 0;
+    let mut background_color = None;
 
     while chunk_reader.position() < size {
         let attribute = read_header_attribute(&mut chunk_reader)?;
 
         match &attribute.key[..] {
             "DATE" => {
+                if attribute.val.len() != 8 {
+                    return Err(ParseError::new("Creation date has to be 8 bytes long"));
+                }
+
                 let unix_secs = BigEndian::read_u64(attribute.val.as_slice());
                 let duration = Duration::from_secs(unix_secs);
-                let date = UNIX_EPOCH + duration;
+                let date = UNIX_EPOCH
+                    .checked_add(duration)
+                    .ok_or_else(|| ParseError::new("Creation date is out of range"))?;
 
                 creation_date = Some(date);
             }
-            "WIDT" => width = BigEndian::read_u16(attribute.val.as_slice()),
-            "HEIG" => height = BigEndian::read_u16(attribute.val.as_slice()),
+            "WIDT" | "HEIG" => {
+                if attribute.val.len() != 2 {
+                    return Err(ParseError::new("Image dimensions have to be 2 bytes long"));
+                }
+
+                let value = BigEndian::read_u16(attribute.val.as_slice());
+                match &attribute.key[..] {
+                    "WIDT" => width = value,
+                    _ => height = value,
+                }
+            }
+            "BGCL" => {
+                if attribute.val.len() != 3 {
+                    return Err(ParseError::new("Background color has to be 3 bytes long"));
+                }
+
+                background_color =
+                    Some([attribute.val[0], attribute.val[1], attribute.val[2]].into());
+            }
+            "SIZE" => (), // Recomputed when writing
             _ => other_attributes.push(attribute),
         }
     }
@@ -93,79 +181,1052 @@ fn parse_header_chunk(chunk: Chunk) -> Result<HeaderChunk, ParseError> {
         other_attributes,
         width,
         height,
+        background_color,
     })
 }
 
-fn parse_data_chunk(chunk: Chunk) -> Result<DataChunk, ParseError> {
-    if chunk.code != "DATA" {
-        return Err(ParseError::new("Expected data chunk"));
+// Runs are stored as a count byte followed by the color of the run
+fn decompress_pixels(data: &[u8], pixels_len: usize) -> Result<Vec<u8>, ParseError> {
+    let error = || ParseError::new("Pixel block size does not match its dimensions");
+
+    if !data.len().is_multiple_of(4) {
+        return Err(error());
+    }
+
+    let mut pixels = Vec::new();
+
+    for run in data.chunks(4) {
+        // Stop before a few bytes expand into more pixels than the block has
+        if pixels.len() + run[0] as usize * 3 > pixels_len {
+            return Err(error());
+        }
+
+        for _ in 0..run[0] {
+            pixels.extend_from_slice(&run[1..]);
+        }
     }
 
+    Ok(pixels)
+}
+
+// Symbol masks are stored row by row with one bit per pixel, rows are padded
+// to whole bytes
+pub fn parse_symbol_chunk(chunk: Chunk, limits: &ReadLimits) -> Result<SymbolChunk, ParseError> {
     let total_size = chunk.data.len() as u64;
     let mut chunk_reader = Cursor::new(chunk.data);
 
-    let mut objects = Vec::new();
+    let count = read_varint(&mut chunk_reader)?;
+    let mut pixels = 0;
 
-    while chunk_reader.position() < total_size {
-        let start_pos = chunk_reader.position();
-        let size = chunk_reader.read_u32::<BigEndian>()?;
-        let obj_type = chunk_reader.read_u8()? as char;
+    let mut symbols = Vec::new();
+    for _ in 0..count {
+        let width = read_varint_u16(&mut chunk_reader)?;
+        let height = read_varint_u16(&mut chunk_reader)?;
 
-        let object = match obj_type {
-            'P' => {
-                let mut color_bytes = [0; 3];
-                chunk_reader.read_exact(&mut color_bytes)?;
-                let color = color_bytes.into();
+        // Masks take a value per pixel, so they are checked before expanding
+        pixels += width as u64 * height as u64;
+        limits.check_symbols(symbols.len() + 1, pixels)?;
+
+        let row_size = (width as u64).div_ceil(8);
+        if row_size * height as u64 > total_size - chunk_reader.position() {
+            return Err(ParseError::new("Symbol mask is larger than its chunk"));
+        }
+
+        let mut rows = vec![0u8; (row_size * height as u64) as usize];
+        chunk_reader.read_exact(&mut rows)?;
+
+        let mask = rows
+            .chunks(row_size.max(1) as usize)
+            .flat_map(|row| (0..width as usize).map(move |x| row[x / 8] & (0x80 >> (x % 8)) != 0))
+            .collect();
+
+        symbols.push(Symbol {
+            width,
+            height,
+            mask,
+        });
+    }
+
+    Ok(SymbolChunk { symbols })
+}
+
+fn read_extent(chunk_reader: &mut Cursor<Vec<u8>>) -> Result<Region, ParseError> {
+    let start_pos = chunk_reader.position();
+    let size = chunk_reader.read_u32::<BigEndian>()?;
+    let _obj_type = chunk_reader.read_u8()?;
+
+    let x = chunk_reader.read_u16::<BigEndian>()?;
+    let y = chunk_reader.read_u16::<BigEndian>()?;
+    let width = chunk_reader.read_u16::<BigEndian>()?;
+    let height = chunk_reader.read_u16::<BigEndian>()?;
+
+    skip_to_object_end(chunk_reader, start_pos, size)?;
+
+    Ok(Region {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+// Records may end with data this version doesn't know about, which is skipped
+// using the size of the record
+fn skip_to_object_end(
+    chunk_reader: &mut Cursor<Vec<u8>>,
+    start_pos: u64,
+    size: u32,
+) -> Result<(), ParseError> {
+    let end_pos = start_pos + size as u64;
+    if chunk_reader.position() > end_pos {
+        return Err(ParseError::new("Object is larger than its size"));
+    }
+    if end_pos > chunk_reader.get_ref().len() as u64 {
+        return Err(ParseError::new("Object is larger than its chunk"));
+    }
+
+    chunk_reader.set_position(end_pos);
+    Ok(())
+}
+
+// Pixels of the pixel blocks read so far, which are limited together as a few
+// bytes of runs can expand into a whole block
+#[derive(Clone, Copy)]
+pub(super) struct BlockPixels {
+    pixels: u64,
+    image_pixels: u64,
+}
+
+impl BlockPixels {
+    pub(super) fn new(header_chunk: &HeaderChunk) -> Self {
+        Self {
+            pixels: 0,
+            image_pixels: header_chunk.width as u64 * header_chunk.height as u64,
+        }
+    }
+
+    fn add(&mut self, width: u16, height: u16, limits: &ReadLimits) -> Result<(), ParseError> {
+        self.pixels += width as u64 * height as u64;
+        limits.check_block_pixels(self.pixels, self.image_pixels)?;
+
+        Ok(())
+    }
+}
+
+fn read_object(
+    chunk_reader: &mut Cursor<Vec<u8>>,
+    limits: &ReadLimits,
+    block_pixels: &mut BlockPixels,
+) -> Result<Object, ParseError> {
+    let start_pos = chunk_reader.position();
+    let size = chunk_reader.read_u32::<BigEndian>()?;
+    let obj_type = chunk_reader.read_u8()? as char;
+
+    let object = match obj_type {
+        'E' => {
+            return Err(ParseError::new(
+                "Extent has to be the first record of a data chunk",
+            ))
+        }
+        'P' => {
+            let mut color_bytes = [0; 3];
+            chunk_reader.read_exact(&mut color_bytes)?;
+            let color = color_bytes.into();
+
+            let mut points: Vec<(u16, u16)> = Vec::new();
+            while chunk_reader.position() < start_pos + size as u64 {
+                let x = chunk_reader.read_u16::<BigEndian>()?;
+                let y = chunk_reader.read_u16::<BigEndian>()?;
+
+                points.push((x, y));
+            }
+
+            Object::Path(PathObject { color, points })
+        }
+        'M' => {
+            let mut color_bytes = [0; 3];
+            chunk_reader.read_exact(&mut color_bytes)?;
+            let color = color_bytes.into();
+
+            let fill_rule = match chunk_reader.read_u8()? {
+                0 => FillRule::NonZero,
+                1 => FillRule::EvenOdd,
+                fill_rule => {
+                    return Err(ParseError::new(&format!(
+                        "Unexpected fill rule {}",
+                        fill_rule
+                    )))
+                }
+            };
+
+            let ring_count = read_varint(chunk_reader)?;
+            let mut total_points: usize = 0;
+
+            let mut rings = Vec::new();
+            for _ in 0..ring_count {
+                let point_count = read_varint(chunk_reader)?;
+
+                // Rings count as a point each, so that empty rings can't pile up
+                total_points = total_points
+                    .saturating_add(usize::try_from(point_count).unwrap_or(usize::MAX))
+                    .saturating_add(1);
+                limits.check_points(total_points)?;
 
                 let mut points: Vec<(u16, u16)> = Vec::new();
-                while chunk_reader.position() < start_pos + size as u64 {
+                for _ in 0..point_count {
                     let x = chunk_reader.read_u16::<BigEndian>()?;
                     let y = chunk_reader.read_u16::<BigEndian>()?;
 
                     points.push((x, y));
                 }
 
-                Object::Path(PathObject { color, points })
+                rings.push(points);
             }
-            _ => {
-                return Err(ParseError::new(&format!(
-                    "Unexpected object type with code '{}'",
-                    obj_type
-                )))
+
+            Object::CompoundPath(CompoundPathObject {
+                color,
+                fill_rule,
+                rings,
+            })
+        }
+        'R' => {
+            let mut color_bytes = [0; 3];
+            chunk_reader.read_exact(&mut color_bytes)?;
+            let color = color_bytes.into();
+
+            let x = chunk_reader.read_u16::<BigEndian>()?;
+            let y = chunk_reader.read_u16::<BigEndian>()?;
+            let width = chunk_reader.read_u16::<BigEndian>()?;
+            let height = chunk_reader.read_u16::<BigEndian>()?;
+
+            Object::Rect(RectObject {
+                color,
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+        'B' => {
+            let x = chunk_reader.read_u16::<BigEndian>()?;
+            let y = chunk_reader.read_u16::<BigEndian>()?;
+            let width = chunk_reader.read_u16::<BigEndian>()?;
+            let height = chunk_reader.read_u16::<BigEndian>()?;
+            let compression = chunk_reader.read_u8()?;
+
+            // Checked before reading, as a few bytes of runs can expand into
+            // all of the pixels
+            limits.check_dimensions(width, height)?;
+            block_pixels.add(width, height, limits)?;
+
+            let end_pos = start_pos + size as u64;
+            if end_pos < chunk_reader.position() || end_pos > chunk_reader.get_ref().len() as u64 {
+                return Err(ParseError::new("Pixel block is larger than its chunk"));
+            }
+
+            let mut data = vec![0u8; (end_pos - chunk_reader.position()) as usize];
+            chunk_reader.read_exact(&mut data)?;
+
+            let pixels_len = width as usize * height as usize * 3;
+            let pixels = match compression {
+                0 => data,
+                1 => decompress_pixels(&data, pixels_len)?,
+                _ => {
+                    return Err(ParseError::new(&format!(
+                        "Unexpected pixel block compression {}",
+                        compression
+                    )))
+                }
+            };
+
+            if pixels.len() != pixels_len {
+                return Err(ParseError::new(
+                    "Pixel block size does not match its dimensions",
+                ));
             }
+
+            Object::PixelBlock(PixelBlockObject {
+                x,
+                y,
+                width,
+                height,
+                pixels,
+            })
+        }
+        'S' => {
+            let count = read_varint(chunk_reader)?;
+            limits.check_runs(usize::try_from(count).unwrap_or(usize::MAX))?;
+
+            let mut runs = Vec::new();
+            for _ in 0..count {
+                let y = read_varint_u16(chunk_reader)?;
+                let x_start = read_varint_u16(chunk_reader)?;
+                let length = read_varint_u16(chunk_reader)?;
+
+                let mut color_bytes = [0; 3];
+                chunk_reader.read_exact(&mut color_bytes)?;
+                let color = color_bytes.into();
+
+                runs.push(Run {
+                    y,
+                    x_start,
+                    length,
+                    color,
+                });
+            }
+
+            Object::Runs(RunsObject { runs })
+        }
+        'G' => {
+            let start_x = chunk_reader.read_u16::<BigEndian>()?;
+            let start_y = chunk_reader.read_u16::<BigEndian>()?;
+            let end_x = chunk_reader.read_u16::<BigEndian>()?;
+            let end_y = chunk_reader.read_u16::<BigEndian>()?;
+
+            let mut color_bytes = [0; 6];
+            chunk_reader.read_exact(&mut color_bytes)?;
+            let start_color = [color_bytes[0], color_bytes[1], color_bytes[2]].into();
+            let end_color = [color_bytes[3], color_bytes[4], color_bytes[5]].into();
+
+            let mut points: Vec<(u16, u16)> = Vec::new();
+            while chunk_reader.position() < start_pos + size as u64 {
+                let x = chunk_reader.read_u16::<BigEndian>()?;
+                let y = chunk_reader.read_u16::<BigEndian>()?;
+
+                points.push((x, y));
+            }
+
+            Object::LinearGradient(LinearGradientObject {
+                start: (start_x, start_y),
+                end: (end_x, end_y),
+                start_color,
+                end_color,
+                points,
+            })
+        }
+        'C' => {
+            let x = chunk_reader.read_u16::<BigEndian>()?;
+            let y = chunk_reader.read_u16::<BigEndian>()?;
+            let width = chunk_reader.read_u16::<BigEndian>()?;
+            let height = chunk_reader.read_u16::<BigEndian>()?;
+            let dst_x = chunk_reader.read_u16::<BigEndian>()?;
+            let dst_y = chunk_reader.read_u16::<BigEndian>()?;
+
+            // Decoding copies may hold their whole source
+            limits.check_dimensions(width, height)?;
+
+            Object::Copy(CopyObject {
+                src: Region {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                dst: (dst_x, dst_y),
+            })
+        }
+        'Y' => {
+            let symbol = read_varint_u32(chunk_reader)?;
+            let x = chunk_reader.read_u16::<BigEndian>()?;
+            let y = chunk_reader.read_u16::<BigEndian>()?;
+
+            let mut color_bytes = [0; 3];
+            chunk_reader.read_exact(&mut color_bytes)?;
+            let color = color_bytes.into();
+
+            Object::Symbol(SymbolObject {
+                symbol,
+                x,
+                y,
+                color,
+            })
+        }
+        _ => {
+            return Err(ParseError::new(&format!(
+                "Unexpected object type with code '{}'",
+                obj_type
+            )))
+        }
+    };
+
+    skip_to_object_end(chunk_reader, start_pos, size)?;
+
+    Ok(object)
+}
+
+// Reads the next record of a data chunk on its own, so that only the record
+// is held in memory instead of the whole chunk
+fn read_record<R: Read>(reader: &mut R, remaining: &mut u64) -> Result<Vec<u8>, ParseError> {
+    let size = reader.read_u32::<BigEndian>()?;
+    if size < 5 {
+        return Err(ParseError::new("Object is larger than its size"));
+    }
+    if size as u64 > *remaining {
+        return Err(ParseError::new("Object is larger than its chunk"));
+    }
+
+    let mut record = size.to_be_bytes().to_vec();
+    reader.take(size as u64 - 4).read_to_end(&mut record)?;
+    if record.len() != size as usize {
+        return Err(ParseError::new("Unexpected end of chunk"));
+    }
+
+    *remaining -= size as u64;
+    Ok(record)
+}
+
+// Parses the objects of a data chunk one at a time, the extent of the chunk is
+// read up front since it has to be its first record. Objects are read straight
+// from the reader, which holds either a loaded chunk or the data of a chunk
+// streamed by ChunkReader.
+pub struct ObjectIter<R = Cursor<Vec<u8>>> {
+    reader: R,
+    remaining: u64,
+    first_record: Option<Vec<u8>>,
+    extent: Option<Region>,
+    limits: ReadLimits,
+    block_pixels: BlockPixels,
+}
+
+impl ObjectIter {
+    pub fn new(chunk: Chunk) -> Result<Self, ParseError> {
+        Self::with_limits(chunk, ReadLimits::default())
+    }
+
+    pub fn with_limits(chunk: Chunk, limits: ReadLimits) -> Result<Self, ParseError> {
+        if chunk.code != "DATA" {
+            return Err(ParseError::new("Expected data chunk"));
+        }
+
+        let size = chunk.data.len() as u64;
+
+        // The size of the image isn't known here
+        let block_pixels = BlockPixels {
+            pixels: 0,
+            image_pixels: u64::MAX,
+        };
+
+        ObjectIter::from_reader(Cursor::new(chunk.data), size, limits, block_pixels)
+    }
+}
+
+impl<R: Read> ObjectIter<R> {
+    fn from_reader(
+        mut reader: R,
+        size: u64,
+        limits: ReadLimits,
+        block_pixels: BlockPixels,
+    ) -> Result<Self, ParseError> {
+        let mut remaining = size;
+
+        let mut first_record = None;
+        let mut extent = None;
+        if remaining > 0 {
+            let record = read_record(&mut reader, &mut remaining)?;
+
+            match record[4] {
+                b'E' => extent = Some(read_extent(&mut Cursor::new(record))?),
+                _ => first_record = Some(record),
+            }
+        }
+
+        Ok(Self {
+            reader,
+            remaining,
+            first_record,
+            extent,
+            limits,
+            block_pixels,
+        })
+    }
+
+    pub fn extent(&self) -> Option<Region> {
+        self.extent
+    }
+}
+
+impl<R: Read> Iterator for ObjectIter<R> {
+    type Item = Result<Object, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.first_record.take() {
+            Some(record) => Ok(record),
+            None if self.remaining == 0 => return None,
+            None => read_record(&mut self.reader, &mut self.remaining),
         };
 
+        let object = record.and_then(|record| {
+            read_object(
+                &mut Cursor::new(record),
+                &self.limits,
+                &mut self.block_pixels,
+            )
+        });
+
+        // The rest of the chunk can't be parsed after an error
+        if object.is_err() {
+            self.remaining = 0;
+        }
+
+        Some(object)
+    }
+}
+
+// The pixels of blocks in earlier chunks are passed in, and the ones of this
+// chunk added to them
+pub(super) fn parse_data_chunk(
+    chunk: Chunk,
+    limits: &ReadLimits,
+    block_pixels: &mut BlockPixels,
+) -> Result<DataChunk, ParseError> {
+    let mut object_iter = ObjectIter::with_limits(chunk, *limits)?;
+    object_iter.block_pixels = *block_pixels;
+    let extent = object_iter.extent();
+
+    let mut objects = Vec::new();
+    for object in &mut object_iter {
+        let object = object?;
+        limits.check_object(&object)?;
+
         objects.push(object);
+        limits.check_object_count(objects.len())?;
     }
 
-    Ok(DataChunk { objects })
+    *block_pixels = object_iter.block_pixels;
+    Ok(DataChunk { extent, objects })
 }
 
-pub fn read(path: &str) -> Result<Entity, ParseError> {
-    let file = File::open(path)?;
-    let file_size = file.metadata().unwrap().len();
-    let mut reader = BufReader::new(file);
+pub fn read_header_from<R: BufRead>(reader: &mut R) -> Result<HeaderChunk, ParseError> {
+    read_signature(reader)?;
+
+    let limits = ReadLimits::default();
+    let header_chunk = parse_header_chunk(read_chunk(reader, &limits)?)?;
+    limits.check_dimensions(header_chunk.width, header_chunk.height)?;
+
+    Ok(header_chunk)
+}
+
+// Reads the chunks following the header one at a time up to the end chunk, so
+// that large files can be scanned without holding all of their chunks
+pub struct ChunkReader<R> {
+    reader: R,
+    version: String,
+    header_chunk: HeaderChunk,
+    limits: ReadLimits,
+    unread: u64,
+    is_finished: bool,
+}
+
+// Data of the chunk being streamed, whatever isn't read from it is skipped
+// before the next chunk
+pub struct ChunkData<'a, R> {
+    reader: &'a mut R,
+    unread: &'a mut u64,
+}
+
+impl<R: Read> Read for ChunkData<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = buf
+            .len()
+            .min(usize::try_from(*self.unread).unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..length])?;
+        *self.unread -= read as u64;
+
+        Ok(read)
+    }
+}
+
+pub enum StreamedChunk<'a, R> {
+    Data(ObjectIter<ChunkData<'a, R>>),
+    Other(Chunk),
+}
+
+impl<R: BufRead> ChunkReader<R> {
+    pub fn new(reader: R) -> Result<Self, ParseError> {
+        Self::with_limits(reader, ReadLimits::default())
+    }
+
+    pub fn with_limits(mut reader: R, limits: ReadLimits) -> Result<Self, ParseError> {
+        let version = read_signature(&mut reader)?;
+        let header_chunk = parse_header_chunk(read_chunk(&mut reader, &limits)?)?;
+        limits.check_dimensions(header_chunk.width, header_chunk.height)?;
 
-    let version = read_signature(&mut reader)?;
+        Ok(Self {
+            reader,
+            version,
+            header_chunk,
+            limits,
+            unread: 0,
+            is_finished: false,
+        })
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn header_chunk(&self) -> &HeaderChunk {
+        &self.header_chunk
+    }
 
-    let header_chunk = parse_header_chunk(read_chunk(&mut reader)?)?;
+    fn skip_unread(&mut self) -> Result<(), ParseError> {
+        let unread = self.unread;
+        self.unread = 0;
 
+        let skipped = io::copy(&mut (&mut self.reader).take(unread), &mut io::sink())?;
+        if skipped != unread {
+            return Err(ParseError::new("Unexpected end of chunk"));
+        }
+
+        Ok(())
+    }
+
+    // Like next, but the objects of data chunks are parsed straight from the
+    // reader as they are iterated, so that only one object is held in memory at
+    // a time no matter how large the chunk is
+    pub fn next_streamed(&mut self) -> Option<Result<StreamedChunk<'_, R>, ParseError>> {
+        if self.is_finished {
+            return None;
+        }
+
+        let header = self
+            .skip_unread()
+            .and_then(|_| match self.reader.fill_buf() {
+                Ok([]) => Ok(None),
+                Ok(_) => read_chunk_header(&mut self.reader, &self.limits).map(Some),
+                Err(err) => Err(err.into()),
+            });
+
+        let (code, size) = match header {
+            Ok(Some(header)) => header,
+            Ok(None) => {
+                self.is_finished = true;
+                return None;
+            }
+            Err(err) => {
+                self.is_finished = true;
+                return Some(Err(err));
+            }
+        };
+
+        match &code[..] {
+            "DATA" => {
+                self.unread = size as u64;
+                let data = ChunkData {
+                    reader: &mut self.reader,
+                    unread: &mut self.unread,
+                };
+                let block_pixels = BlockPixels::new(&self.header_chunk);
+
+                Some(
+                    ObjectIter::from_reader(data, size as u64, self.limits, block_pixels)
+                        .map(StreamedChunk::Data),
+                )
+            }
+            "FEND" => {
+                self.is_finished = true;
+                read_chunk_data(&mut self.reader, code, size).err().map(Err)
+            }
+            _ => Some(read_chunk_data(&mut self.reader, code, size).map(StreamedChunk::Other)),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished {
+            return None;
+        }
+
+        if let Err(err) = self.skip_unread() {
+            self.is_finished = true;
+            return Some(Err(err));
+        }
+
+        let chunk = match self.reader.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(read_chunk(&mut self.reader, &self.limits)),
+            Err(err) => Some(Err(err.into())),
+        };
+
+        match &chunk {
+            Some(Ok(chunk)) if chunk.code != "FEND" => (),
+            _ => {
+                self.is_finished = true;
+                return chunk.filter(|chunk| chunk.is_err());
+            }
+        }
+
+        chunk
+    }
+}
+
+pub fn read_from<R: BufRead>(reader: &mut R) -> Result<Entity, ParseError> {
+    read_from_with_limits(reader, ReadLimits::default())
+}
+
+pub fn read_from_with_limits<R: BufRead>(
+    reader: &mut R,
+    limits: ReadLimits,
+) -> Result<Entity, ParseError> {
+    let mut chunk_reader = ChunkReader::with_limits(reader, limits)?;
+    let mut object_count = 0;
+    let mut block_pixels = BlockPixels::new(chunk_reader.header_chunk());
+
+    let mut symbol_chunk = None;
     let mut other_chunks = Vec::new();
     let mut data_chunks = Vec::new();
 
-    while reader.seek(SeekFrom::Current(0)).unwrap() < file_size {
-        let chunk = read_chunk(&mut reader)?;
+    for chunk in &mut chunk_reader {
+        let chunk = chunk?;
 
         match &chunk.code[..] {
-            "DATA" => data_chunks.push(parse_data_chunk(chunk)?),
+            "SYMB" => symbol_chunk = Some(parse_symbol_chunk(chunk, &limits)?),
+            "DATA" => {
+                let data_chunk = parse_data_chunk(chunk, &limits, &mut block_pixels)?;
+
+                object_count += data_chunk.objects.len();
+                limits.check_object_count(object_count)?;
+
+                data_chunks.push(data_chunk);
+            }
+            "INDX" => (), // Recomputed when writing
             _ => other_chunks.push(chunk),
         }
     }
 
+    let ChunkReader {
+        version,
+        header_chunk,
+        ..
+    } = chunk_reader;
+
     Ok(Entity {
         header_chunk,
         version,
+        symbol_chunk,
         data_chunks,
         other_chunks,
     })
 }
+
+fn parse_index_chunk(chunk: Chunk) -> Result<Vec<IndexEntry>, ParseError> {
+    if chunk.code != "INDX" {
+        return Err(ParseError::new("Expected index chunk"));
+    }
+
+    if !chunk.data.len().is_multiple_of(21) {
+        return Err(ParseError::new("Index entries have to be 21 bytes long"));
+    }
+
+    let mut chunk_reader = Cursor::new(chunk.data);
+    let mut index = Vec::new();
+
+    while chunk_reader.position() < chunk_reader.get_ref().len() as u64 {
+        let offset = chunk_reader.read_u64::<BigEndian>()?;
+        let size = chunk_reader.read_u32::<BigEndian>()?;
+        let has_extent = chunk_reader.read_u8()? != 0;
+
+        let x = chunk_reader.read_u16::<BigEndian>()?;
+        let y = chunk_reader.read_u16::<BigEndian>()?;
+        let width = chunk_reader.read_u16::<BigEndian>()?;
+        let height = chunk_reader.read_u16::<BigEndian>()?;
+
+        index.push(IndexEntry {
+            offset,
+            size,
+            extent: Some(Region {
+                x,
+                y,
+                width,
+                height,
+            })
+            .filter(|_| has_extent),
+        });
+    }
+
+    Ok(index)
+}
+
+// The end chunk of files with an index holds the total size of the file and the
+// offset of the index chunk, files without one have no index
+fn read_index<R: BufRead + Seek>(
+    reader: &mut R,
+    start_pos: u64,
+    limits: &ReadLimits,
+) -> Result<Option<Vec<IndexEntry>>, ParseError> {
+    let end_pos = reader.seek(SeekFrom::End(0))?;
+    if end_pos < start_pos + 24 {
+        return Ok(None);
+    }
+
+    let mut end_chunk = [0u8; 24];
+    reader.seek(SeekFrom::Start(end_pos - 24))?;
+    reader.read_exact(&mut end_chunk)?;
+    if BigEndian::read_u32(&end_chunk[..4]) != 24 || &end_chunk[4..8] != b"FEND" {
+        return Ok(None);
+    }
+
+    let file_size = BigEndian::read_u64(&end_chunk[8..16]);
+    let index_offset = BigEndian::read_u64(&end_chunk[16..]);
+    if file_size != end_pos - start_pos || index_offset >= file_size {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(start_pos + index_offset))?;
+    let index = parse_index_chunk(read_chunk(reader, limits)?)?;
+
+    Ok(Some(index))
+}
+
+// Reads the header, the symbols and only the data chunks drawing to the rect,
+// using the index to seek to them. Chunks can only be left out when their
+// extents are disjoint, otherwise all of them are read.
+pub fn read_chunks_in_rect<R: BufRead + Seek>(
+    reader: &mut R,
+    rect: Region,
+) -> Result<Entity, ParseError> {
+    read_chunks_in_rect_with_limits(reader, rect, ReadLimits::default())
+}
+
+pub fn read_chunks_in_rect_with_limits<R: BufRead + Seek>(
+    reader: &mut R,
+    rect: Region,
+    limits: ReadLimits,
+) -> Result<Entity, ParseError> {
+    let start_pos = reader.stream_position()?;
+    let index = read_index(reader, start_pos, &limits)?;
+    reader.seek(SeekFrom::Start(start_pos))?;
+
+    let extents: Option<Vec<Option<Region>>> = index
+        .as_ref()
+        .map(|index| index.iter().map(|entry| entry.extent).collect());
+
+    let index = match (index, extents) {
+        (Some(index), Some(extents)) if are_disjoint(&extents) => index,
+        _ => {
+            let mut entity = read_from_with_limits(reader, limits)?;
+
+            let extents: Vec<Option<Region>> = entity
+                .data_chunks
+                .iter()
+                .map(|chunk| chunk.extent)
+                .collect();
+            if are_disjoint(&extents) {
+                entity.data_chunks.retain(|chunk| {
+                    chunk
+                        .extent
+                        .is_some_and(|extent| regions_overlap(extent, rect))
+                });
+            }
+
+            return Ok(entity);
+        }
+    };
+
+    let mut chunk_reader = ChunkReader::with_limits(&mut *reader, limits)?;
+
+    // Symbols come right after the header
+    let symbol_chunk = match chunk_reader.next().transpose()? {
+        Some(chunk) if chunk.code == "SYMB" => Some(parse_symbol_chunk(chunk, &limits)?),
+        _ => None,
+    };
+
+    let ChunkReader {
+        version,
+        header_chunk,
+        ..
+    } = chunk_reader;
+
+    let mut object_count = 0;
+    let mut block_pixels = BlockPixels::new(&header_chunk);
+    let mut data_chunks = Vec::new();
+    for entry in index {
+        if !entry
+            .extent
+            .is_some_and(|extent| regions_overlap(extent, rect))
+        {
+            continue;
+        }
+
+        reader.seek(SeekFrom::Start(start_pos + entry.offset))?;
+        let chunk = read_chunk(reader, &limits)?;
+        let data_chunk = parse_data_chunk(chunk, &limits, &mut block_pixels)?;
+
+        object_count += data_chunk.objects.len();
+        limits.check_object_count(object_count)?;
+
+        data_chunks.push(data_chunk);
+    }
+
+    Ok(Entity {
+        header_chunk,
+        version,
+        symbol_chunk,
+        data_chunks,
+        other_chunks: Vec::new(),
+    })
+}
+
+// Files opened by path are trusted like the ones the command line tool reads,
+// which may be as large as the format allows, so they aren't limited. Data
+// from untrusted sources goes through read_from with the default limits.
+pub fn read(path: &str) -> Result<Entity, ParseError> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    read_from_with_limits(&mut reader, ReadLimits::unlimited())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_index_chunk, read_chunks_in_rect, read_from, read_index};
+    use crate::algorithm::encode::encode_tiled;
+    use crate::entity::{Chunk, Entity, Region};
+    use crate::limits::ReadLimits;
+    use crate::s7_image::Image;
+    use crate::serialization::write::tests::entity_with_every_object;
+    use crate::serialization::write::write_stream;
+    use crate::utils::regions_overlap;
+    use byteorder::{BigEndian, ByteOrder};
+    use image::RgbImage;
+    use std::io::Cursor;
+
+    fn tiled_entity() -> Entity {
+        let image = RgbImage::from_fn(96, 64, |x, y| {
+            image::Rgb([
+                (x * 2) as u8,
+                (y * 3) as u8,
+                ((x / 8 + y / 8) % 2 * 255) as u8,
+            ])
+        });
+
+        encode_tiled(Image::new(image), 32)
+    }
+
+    fn extents(entity: &Entity) -> Vec<Option<Region>> {
+        entity
+            .data_chunks
+            .iter()
+            .map(|chunk| chunk.extent)
+            .collect()
+    }
+
+    #[test]
+    fn index() {
+        let entity = tiled_entity();
+        let extents = extents(&entity);
+
+        // Files don't have to start at the beginning of the reader
+        let mut data = b"abc".to_vec();
+        write_stream(&mut data, entity).unwrap();
+
+        let mut reader = Cursor::new(&data);
+        let index = read_index(&mut reader, 3, &ReadLimits::default())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(index.len(), extents.len());
+        for (entry, extent) in index.iter().zip(extents) {
+            let chunk = &data[3 + entry.offset as usize..];
+            assert_eq!(BigEndian::read_u32(chunk), entry.size);
+            assert_eq!(&chunk[4..8], b"DATA");
+            assert_eq!(format!("{:?}", entry.extent), format!("{:?}", extent));
+        }
+
+        // The end chunk holds the size of the file and the offset of the index
+        let end_chunk = &data[data.len() - 24..];
+        assert_eq!(&end_chunk[..8], b"\0\0\0\x18FEND");
+        assert_eq!(BigEndian::read_u64(&end_chunk[8..]), data.len() as u64 - 3);
+        assert_eq!(
+            &data[3 + BigEndian::read_u64(&end_chunk[16..]) as usize..][4..8],
+            b"INDX"
+        );
+    }
+
+    #[test]
+    fn end_chunk_without_index() {
+        let mut entity = entity_with_every_object();
+        entity.data_chunks.truncate(1);
+
+        let mut data = Vec::new();
+        write_stream(&mut data, entity).unwrap();
+
+        let end_chunk = &data[data.len() - 16..];
+        assert_eq!(&end_chunk[..8], b"\0\0\0\x10FEND");
+        assert_eq!(BigEndian::read_u64(&end_chunk[8..]), data.len() as u64);
+
+        let index = read_index(&mut Cursor::new(&data), 0, &ReadLimits::default()).unwrap();
+        assert!(index.is_none());
+    }
+
+    #[test]
+    fn end_chunk_of_other_file() {
+        let mut data = Vec::new();
+        write_stream(&mut data, tiled_entity()).unwrap();
+
+        // Files which were cut or appended to have a different size
+        let size_pos = data.len() - 16;
+        let size = BigEndian::read_u64(&data[size_pos..]);
+        BigEndian::write_u64(&mut data[size_pos..], size + 1);
+
+        let index = read_index(&mut Cursor::new(&data), 0, &ReadLimits::default()).unwrap();
+        assert!(index.is_none());
+        assert!(read_from(&mut Cursor::new(&data)).is_ok());
+    }
+
+    #[test]
+    fn index_entry_size() {
+        let chunk = |length| Chunk {
+            code: "INDX".to_owned(),
+            data: vec![0; length],
+        };
+
+        assert_eq!(parse_index_chunk(chunk(42)).unwrap().len(), 2);
+        assert!(parse_index_chunk(chunk(20)).is_err());
+        assert!(parse_index_chunk(Chunk {
+            code: "DATA".to_owned(),
+            data: Vec::new(),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn chunks_in_rect() {
+        let entity = tiled_entity();
+        let chunk_extents = extents(&entity);
+
+        let mut data = Vec::new();
+        write_stream(&mut data, entity).unwrap();
+
+        let rect = Region {
+            x: 40,
+            y: 20,
+            width: 30,
+            height: 20,
+        };
+        let expected: Vec<Option<Region>> = chunk_extents
+            .into_iter()
+            .filter(|extent| extent.is_some_and(|extent| regions_overlap(extent, rect)))
+            .collect();
+        assert_eq!(expected.len(), 4);
+
+        let entity = read_chunks_in_rect(&mut Cursor::new(&data), rect).unwrap();
+        assert_eq!(format!("{:?}", extents(&entity)), format!("{:?}", expected));
+
+        // Chunks outside of the rect aren't read at all
+        let index = read_index(&mut Cursor::new(&data), 0, &ReadLimits::default())
+            .unwrap()
+            .unwrap();
+        let outside = index
+            .iter()
+            .find(|entry| !regions_overlap(entry.extent.unwrap(), rect))
+            .unwrap();
+        data[outside.offset as usize + 8] = b'X';
+
+        assert!(read_from(&mut Cursor::new(&data)).is_err());
+        assert!(read_chunks_in_rect(&mut Cursor::new(&data), rect).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+use super::parse_error::ParseError;
+use super::read::{
+    check_version, parse_data_chunk, parse_header_chunk, parse_symbol_chunk, BlockPixels,
+};
+use crate::entity::{Chunk, Entity};
+use crate::limits::ReadLimits;
+use byteorder::{BigEndian, ByteOrder};
+use std::str;
+
+// Chunk or header attribute borrowed from the file data, both are stored as
+// their size, a four byte code and the data
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkView<'a> {
+    pub code: &'a str,
+    pub data: &'a [u8],
+}
+
+impl ChunkView<'_> {
+    pub fn to_chunk(self) -> Chunk {
+        Chunk {
+            code: self.code.to_owned(),
+            data: self.data.to_vec(),
+        }
+    }
+}
+
+// Splits the next chunk off the front of the data
+fn split_chunk<'a>(data: &mut &'a [u8]) -> Result<ChunkView<'a>, ParseError> {
+    if data.len() < 8 {
+        return Err(ParseError::new("Unexpected end of data"));
+    }
+
+    let size = BigEndian::read_u32(&data[..4]) as usize;
+    if size < 8 {
+        return Err(ParseError::new("Chunk size is smaller than its header"));
+    }
+    if size > data.len() {
+        return Err(ParseError::new("Chunk is larger than the remaining data"));
+    }
+
+    let code = str::from_utf8(&data[4..8])
+        .map_err(|_| ParseError::new("Chunk code is not valid UTF-8"))?;
+    let chunk = ChunkView {
+        code,
+        data: &data[8..size],
+    };
+
+    *data = &data[size..];
+
+    Ok(chunk)
+}
+
+// Iterates over the chunks or attributes in the data, stopping at the first
+// error
+#[derive(Clone)]
+pub struct ChunkViews<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for ChunkViews<'a> {
+    type Item = Result<ChunkView<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let chunk = split_chunk(&mut self.data);
+        if chunk.is_err() {
+            self.data = &[];
+        }
+
+        Some(chunk)
+    }
+}
+
+// Reads a file in memory, for example a memory mapped one, without copying.
+// The version, the header attributes and the chunks are borrowed from the data.
+#[derive(Clone)]
+pub struct SliceReader<'a> {
+    version: &'a str,
+    header_chunk: ChunkView<'a>,
+    chunks: &'a [u8],
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ParseError> {
+        let data = data
+            .strip_prefix(&[0x0d, b'S', b'7', 0x0d])
+            .ok_or_else(|| ParseError::new("Expected bytes not found"))?;
+
+        let version_end = data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| ParseError::new("Unexpected end of data"))?;
+        let version = str::from_utf8(&data[..version_end])
+            .map_err(|_| ParseError::new("Version is not valid UTF-8"))?;
+        check_version(version)?;
+
+        let mut chunks = &data[version_end + 1..];
+        let header_chunk = split_chunk(&mut chunks)?;
+        if header_chunk.code != "HEAD" {
+            return Err(ParseError::new("Expected header chunk"));
+        }
+
+        Ok(Self {
+            version,
+            header_chunk,
+            chunks,
+        })
+    }
+
+    pub fn version(&self) -> &'a str {
+        self.version
+    }
+
+    pub fn header_attributes(&self) -> ChunkViews<'a> {
+        ChunkViews {
+            data: self.header_chunk.data,
+        }
+    }
+
+    pub fn header_attribute(&self, key: &str) -> Result<Option<&'a [u8]>, ParseError> {
+        for attribute in self.header_attributes() {
+            let attribute = attribute?;
+
+            if attribute.code == key {
+                return Ok(Some(attribute.data));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Chunks following the header, up to the end chunk
+    pub fn chunks(&self) -> impl Iterator<Item = Result<ChunkView<'a>, ParseError>> + 'a {
+        ChunkViews { data: self.chunks }
+            .take_while(|chunk| !matches!(chunk, Ok(chunk) if chunk.code == "FEND"))
+    }
+
+    // Parses the whole file into an entity, which copies the data it holds
+    pub fn read_entity(&self) -> Result<Entity, ParseError> {
+        self.read_entity_with_limits(ReadLimits::default())
+    }
+
+    pub fn read_entity_with_limits(&self, limits: ReadLimits) -> Result<Entity, ParseError> {
+        let header_chunk = parse_header_chunk(self.header_chunk.to_chunk())?;
+        limits.check_dimensions(header_chunk.width, header_chunk.height)?;
+
+        let mut object_count = 0;
+        let mut block_pixels = BlockPixels::new(&header_chunk);
+        let mut symbol_chunk = None;
+        let mut other_chunks = Vec::new();
+        let mut data_chunks = Vec::new();
+
+        for chunk in self.chunks() {
+            let chunk = chunk?;
+            limits.check_chunk_size(chunk.data.len() as u32)?;
+
+            match chunk.code {
+                "SYMB" => symbol_chunk = Some(parse_symbol_chunk(chunk.to_chunk(), &limits)?),
+                "DATA" => {
+                    let data_chunk =
+                        parse_data_chunk(chunk.to_chunk(), &limits, &mut block_pixels)?;
+
+                    object_count += data_chunk.objects.len();
+                    limits.check_object_count(object_count)?;
+
+                    data_chunks.push(data_chunk);
+                }
+                "INDX" => (), // Recomputed when writing
+                _ => other_chunks.push(chunk.to_chunk()),
+            }
+        }
+
+        Ok(Entity {
+            header_chunk,
+            version: self.version.to_owned(),
+            symbol_chunk,
+            data_chunks,
+            other_chunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SliceReader;
+    use crate::entity::VERSION;
+    use crate::serialization::read::read_from;
+    use crate::serialization::write::tests::entity_with_every_object;
+    use crate::serialization::write::write_stream;
+    use std::io::Cursor;
+
+    fn file_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        write_stream(&mut data, entity_with_every_object()).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn same_as_read_from() {
+        let data = file_data();
+
+        let entity = SliceReader::new(&data).unwrap().read_entity().unwrap();
+        assert_eq!(
+            format!("{:?}", entity),
+            format!("{:?}", read_from(&mut Cursor::new(&data)).unwrap())
+        );
+    }
+
+    #[test]
+    fn borrowed_views() {
+        let data = file_data();
+        let reader = SliceReader::new(&data).unwrap();
+
+        assert_eq!(reader.version(), VERSION);
+
+        let note = reader.header_attribute("NOTE").unwrap().unwrap();
+        assert_eq!(note, b"note");
+        assert!(data.as_ptr_range().contains(&note.as_ptr()));
+        assert!(reader.header_attribute("MISS").unwrap().is_none());
+
+        let codes: Vec<&str> = reader.chunks().map(|chunk| chunk.unwrap().code).collect();
+        assert_eq!(codes, ["SYMB", "DATA", "DATA", "TEXT", "INDX"]);
+    }
+
+    // Files cut off anywhere are rejected exactly when read_from rejects them
+    #[test]
+    fn truncated() {
+        let data = file_data();
+
+        for length in 0..data.len() {
+            let data = &data[..length];
+
+            let entity = SliceReader::new(data).and_then(|reader| reader.read_entity());
+            let expected = read_from(&mut Cursor::new(data));
+
+            assert_eq!(entity.is_ok(), expected.is_ok(), "length {}", length);
+        }
+    }
+}
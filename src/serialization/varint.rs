@@ -0,0 +1,136 @@
+use super::parse_error::ParseError;
+use byteorder::ReadBytesExt;
+use std::io::prelude::*;
+
+// Unsigned LEB128, 7 bits per byte with the high bit set on all but the last byte
+pub fn write_varint(data: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        data.push((val as u8 & 0x7f) | 0x80);
+        val >>= 7;
+    }
+
+    data.push(val as u8);
+}
+
+// Number of bytes write_varint takes for the value
+pub fn varint_len(val: u64) -> usize {
+    let bits = 64 - val.leading_zeros() as usize;
+
+    bits.div_ceil(7).max(1)
+}
+
+pub fn read_varint<R: Read>(reader: &mut R) -> Result<u64, ParseError> {
+    let mut val = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8()?;
+
+        // Only the lowest bit of the tenth byte is left for the value
+        if shift == 63 && byte & 0x7f > 1 {
+            return Err(ParseError::new("Varint does not fit into 64 bits"));
+        }
+        val |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+
+    Err(ParseError::new("Varint is too long"))
+}
+
+pub fn read_varint_u16<R: Read>(reader: &mut R) -> Result<u16, ParseError> {
+    let val = read_varint(reader)?;
+
+    if val > u16::MAX as u64 {
+        return Err(ParseError::new("Varint does not fit into 16 bits"));
+    }
+
+    Ok(val as u16)
+}
+
+pub fn read_varint_u32<R: Read>(reader: &mut R) -> Result<u32, ParseError> {
+    let val = read_varint(reader)?;
+
+    if val > u32::MAX as u64 {
+        return Err(ParseError::new("Varint does not fit into 32 bits"));
+    }
+
+    Ok(val as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_varint, read_varint_u16, read_varint_u32, varint_len, write_varint};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let values = [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            u16::MAX as u64,
+            u32::MAX as u64,
+            1 << 63,
+            u64::MAX,
+        ];
+
+        for val in values {
+            let mut data = Vec::new();
+            write_varint(&mut data, val);
+
+            assert_eq!(data.len(), varint_len(val));
+            assert_eq!(read_varint(&mut Cursor::new(&data)).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn encoded_length() {
+        assert_eq!(varint_len(0), 1);
+        assert_eq!(varint_len(0x7f), 1);
+        assert_eq!(varint_len(0x80), 2);
+        assert_eq!(varint_len(u64::MAX), 10);
+    }
+
+    #[test]
+    fn too_long() {
+        // Eleven bytes
+        let mut data = vec![0x80; 10];
+        data.push(0);
+        assert!(read_varint(&mut Cursor::new(&data)).is_err());
+
+        // The tenth byte may only set the highest bit of the value
+        let mut data = vec![0xff; 9];
+        data.push(0x01);
+        assert_eq!(read_varint(&mut Cursor::new(&data)).unwrap(), u64::MAX);
+
+        let mut data = vec![0xff; 9];
+        data.push(0x02);
+        assert!(read_varint(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(read_varint(&mut Cursor::new(&[])).is_err());
+        assert!(read_varint(&mut Cursor::new(&[0x80, 0x80])).is_err());
+    }
+
+    #[test]
+    fn narrow_values() {
+        let mut data = Vec::new();
+        write_varint(&mut data, u16::MAX as u64);
+        write_varint(&mut data, u16::MAX as u64 + 1);
+        write_varint(&mut data, u32::MAX as u64);
+        write_varint(&mut data, u32::MAX as u64 + 1);
+
+        let mut reader = Cursor::new(&data);
+        assert_eq!(read_varint_u16(&mut reader).unwrap(), u16::MAX);
+        assert!(read_varint_u16(&mut reader).is_err());
+        assert_eq!(read_varint_u32(&mut reader).unwrap(), u32::MAX);
+        assert!(read_varint_u32(&mut reader).is_err());
+    }
+}
@@ -1,22 +1,25 @@
-use crate::entity::{DataChunk, Entity, HeaderAttibute, HeaderChunk, Object};
+use super::varint::write_varint;
+use crate::entity::{
+    Chunk, Entity, FillRule, HeaderAttibute, HeaderChunk, IndexEntry, Object, Region, SymbolChunk,
+};
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::io::SeekFrom;
+use std::io::{BufWriter, SeekFrom};
 use std::time::UNIX_EPOCH;
 
-fn write_chunk(file: &mut File, code: &str, data: Vec<u8>) {
+fn write_chunk<W: Write>(file: &mut W, code: &str, data: Vec<u8>) -> io::Result<()> {
     // Chunk size
-    file.write_all(&(data.len() as u32 + 8).to_be_bytes())
-        .unwrap();
+    file.write_all(&(data.len() as u32 + 8).to_be_bytes())?;
 
     // Chunk code
-    file.write_all(code[..4].as_bytes()).unwrap();
+    file.write_all(&code.as_bytes()[..4])?;
 
     // Data
-    file.write_all(data.as_slice()).unwrap();
+    file.write_all(data.as_slice())
 }
 
-fn write_header_chunk(file: &mut File, header: HeaderChunk) {
+fn write_header_chunk<W: Write>(file: &mut W, header: HeaderChunk) -> io::Result<()> {
     let mut all_attributes = vec![
         HeaderAttibute {
             key: "SIZE".to_owned(),
@@ -32,6 +35,14 @@ fn write_header_chunk(file: &mut File, header: HeaderChunk) {
         },
     ];
 
+    if let Some(color) = header.background_color {
+        let color: [u8; 3] = color.into();
+        all_attributes.push(HeaderAttibute {
+            key: "BGCL".to_owned(),
+            val: color.to_vec(),
+        });
+    }
+
     all_attributes.extend_from_slice(header.other_attributes.as_slice());
 
     match header.creation_date {
@@ -55,66 +66,607 @@ fn write_header_chunk(file: &mut File, header: HeaderChunk) {
         data.extend_from_slice(&attr_size.to_be_bytes()[..]);
 
         // Key
-        data.extend_from_slice(attr.key[..4].as_bytes());
+        data.extend_from_slice(&attr.key.as_bytes()[..4]);
 
         // Value
         data.extend_from_slice(&attr.val[..]);
     }
 
-    write_chunk(file, "HEAD", data);
+    write_chunk(file, "HEAD", data)
+}
+
+// Runs are stored as a count byte followed by the color of the run
+fn compress_pixels(pixels: &[u8]) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+
+    for pixel in pixels.chunks(3) {
+        let len = data.len();
+        if len > 0 && data[len - 4] < 255 && data[len - 3..] == *pixel {
+            data[len - 4] += 1;
+        } else {
+            data.push(1);
+            data.extend_from_slice(pixel);
+        }
+    }
+
+    data
 }
 
-fn write_data_chunk(file: &mut File, chunk: DataChunk) {
+// Symbol masks are stored row by row with one bit per pixel, rows are padded
+// to whole bytes
+fn write_symbol_chunk<W: Write>(file: &mut W, chunk: SymbolChunk) -> io::Result<()> {
     let mut data: Vec<u8> = Vec::new();
 
-    for obj in chunk.objects {
-        match obj {
-            Object::Path(path) => {
-                // Size
-                let size = 8 + path.points.len() as u32 * 4;
-                data.extend_from_slice(&size.to_be_bytes());
+    write_varint(&mut data, chunk.symbols.len() as u64);
 
-                // Type
-                data.push('P' as u8);
+    for symbol in chunk.symbols {
+        write_varint(&mut data, symbol.width as u64);
+        write_varint(&mut data, symbol.height as u64);
 
-                // Color
-                let color: [u8; 3] = path.color.into();
-                data.extend_from_slice(&color);
+        let (width, height) = (symbol.width as usize, symbol.height as usize);
 
-                // Points
-                for (x, y) in path.points {
-                    data.extend_from_slice(&[x.to_be_bytes(), y.to_be_bytes()].concat());
+        for y in 0..height {
+            let mut row_bytes = vec![0u8; width.div_ceil(8)];
+
+            for x in 0..width {
+                if symbol.mask.get(x + y * width) == Some(&true) {
+                    row_bytes[x / 8] |= 0x80 >> (x % 8);
                 }
             }
+
+            data.extend_from_slice(&row_bytes);
         }
     }
 
-    write_chunk(file, "DATA", data);
+    write_chunk(file, "SYMB", data)
 }
 
-pub fn write(out_path: &str, entity: Entity) {
-    let mut file = File::create(out_path).unwrap();
+fn write_extent_data(data: &mut Vec<u8>, extent: Region) {
+    // Size
+    data.extend_from_slice(&13u32.to_be_bytes());
+
+    // Type
+    data.push(b'E');
+
+    for val in &[extent.x, extent.y, extent.width, extent.height] {
+        data.extend_from_slice(&val.to_be_bytes());
+    }
+}
+
+fn write_object_data(data: &mut Vec<u8>, object: Object) {
+    match object {
+        Object::Path(path) => {
+            // Size
+            let size = 8 + path.points.len() as u32 * 4;
+            data.extend_from_slice(&size.to_be_bytes());
+
+            // Type
+            data.push(b'P');
+
+            // Color
+            let color: [u8; 3] = path.color.into();
+            data.extend_from_slice(&color);
+
+            // Points
+            for (x, y) in path.points {
+                data.extend_from_slice(&[x.to_be_bytes(), y.to_be_bytes()].concat());
+            }
+        }
+        Object::CompoundPath(path) => {
+            let mut rings_data: Vec<u8> = Vec::new();
+
+            write_varint(&mut rings_data, path.rings.len() as u64);
+
+            for ring in path.rings {
+                write_varint(&mut rings_data, ring.len() as u64);
+
+                for (x, y) in ring {
+                    rings_data.extend_from_slice(&[x.to_be_bytes(), y.to_be_bytes()].concat());
+                }
+            }
+
+            // Size
+            let size = 9 + rings_data.len() as u32;
+            data.extend_from_slice(&size.to_be_bytes());
+
+            // Type
+            data.push(b'M');
+
+            // Color
+            let color: [u8; 3] = path.color.into();
+            data.extend_from_slice(&color);
+
+            // Fill rule
+            data.push(match path.fill_rule {
+                FillRule::NonZero => 0,
+                FillRule::EvenOdd => 1,
+            });
+
+            // Rings
+            data.extend_from_slice(&rings_data);
+        }
+        Object::Rect(rect) => {
+            // Size
+            data.extend_from_slice(&16u32.to_be_bytes());
+
+            // Type
+            data.push(b'R');
+
+            // Color
+            let color: [u8; 3] = rect.color.into();
+            data.extend_from_slice(&color);
+
+            // Position and dimensions
+            for val in &[rect.x, rect.y, rect.width, rect.height] {
+                data.extend_from_slice(&val.to_be_bytes());
+            }
+        }
+        Object::PixelBlock(block) => {
+            let compressed = compress_pixels(&block.pixels);
+
+            // Only keep the compressed pixels if they are actually smaller
+            let (compression, pixels) = if compressed.len() < block.pixels.len() {
+                (1u8, compressed)
+            } else {
+                (0u8, block.pixels)
+            };
+
+            // Size
+            let size = 14 + pixels.len() as u32;
+            data.extend_from_slice(&size.to_be_bytes());
+
+            // Type
+            data.push(b'B');
+
+            // Position and dimensions
+            for val in &[block.x, block.y, block.width, block.height] {
+                data.extend_from_slice(&val.to_be_bytes());
+            }
+
+            // Pixels
+            data.push(compression);
+            data.extend_from_slice(&pixels);
+        }
+        Object::Runs(runs) => {
+            let mut runs_data: Vec<u8> = Vec::new();
+
+            write_varint(&mut runs_data, runs.runs.len() as u64);
+
+            for run in runs.runs {
+                write_varint(&mut runs_data, run.y as u64);
+                write_varint(&mut runs_data, run.x_start as u64);
+                write_varint(&mut runs_data, run.length as u64);
+
+                let color: [u8; 3] = run.color.into();
+                runs_data.extend_from_slice(&color);
+            }
+
+            // Size
+            let size = 5 + runs_data.len() as u32;
+            data.extend_from_slice(&size.to_be_bytes());
+
+            // Type
+            data.push(b'S');
+
+            // Runs
+            data.extend_from_slice(&runs_data);
+        }
+        Object::LinearGradient(gradient) => {
+            // Size
+            let size = 19 + gradient.points.len() as u32 * 4;
+            data.extend_from_slice(&size.to_be_bytes());
+
+            // Type
+            data.push(b'G');
+
+            // Gradient line
+            let (start_x, start_y) = gradient.start;
+            let (end_x, end_y) = gradient.end;
+            for val in &[start_x, start_y, end_x, end_y] {
+                data.extend_from_slice(&val.to_be_bytes());
+            }
+
+            // Colors
+            let start_color: [u8; 3] = gradient.start_color.into();
+            let end_color: [u8; 3] = gradient.end_color.into();
+            data.extend_from_slice(&start_color);
+            data.extend_from_slice(&end_color);
+
+            // Points
+            for (x, y) in gradient.points {
+                data.extend_from_slice(&[x.to_be_bytes(), y.to_be_bytes()].concat());
+            }
+        }
+        Object::Copy(copy) => {
+            // Size
+            data.extend_from_slice(&17u32.to_be_bytes());
+
+            // Type
+            data.push(b'C');
+
+            // Source and destination
+            let (dst_x, dst_y) = copy.dst;
+            for val in &[
+                copy.src.x,
+                copy.src.y,
+                copy.src.width,
+                copy.src.height,
+                dst_x,
+                dst_y,
+            ] {
+                data.extend_from_slice(&val.to_be_bytes());
+            }
+        }
+        Object::Symbol(symbol) => {
+            let mut symbol_data: Vec<u8> = Vec::new();
+
+            write_varint(&mut symbol_data, symbol.symbol as u64);
+
+            for val in &[symbol.x, symbol.y] {
+                symbol_data.extend_from_slice(&val.to_be_bytes());
+            }
+
+            let color: [u8; 3] = symbol.color.into();
+            symbol_data.extend_from_slice(&color);
+
+            // Size
+            let size = 5 + symbol_data.len() as u32;
+            data.extend_from_slice(&size.to_be_bytes());
 
-    // Magic number
-    let magic_bytes = &[&[0x0d], "S7".as_bytes(), &[0x0d]].concat();
-    file.write_all(magic_bytes).unwrap();
+            // Type
+            data.push(b'Y');
 
-    // Version
-    file.write_all(entity.version.as_bytes()).unwrap();
-    file.write_all(&[0]).unwrap();
+            // Symbol, position and color
+            data.extend_from_slice(&symbol_data);
+        }
+    }
+}
+
+// Each entry is the offset and size of a data chunk, followed by a flag for
+// whether it has an extent and the extent
+fn index_data(index: &[IndexEntry]) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+
+    for entry in index {
+        data.extend_from_slice(&entry.offset.to_be_bytes());
+        data.extend_from_slice(&entry.size.to_be_bytes());
+
+        let (flag, extent) = match entry.extent {
+            Some(extent) => (1, extent),
+            None => (
+                0,
+                Region {
+                    x: 0,
+                    y: 0,
+                    width: 0,
+                    height: 0,
+                },
+            ),
+        };
+
+        data.push(flag);
+        for val in &[extent.x, extent.y, extent.width, extent.height] {
+            data.extend_from_slice(&val.to_be_bytes());
+        }
+    }
+
+    data
+}
+
+// Writes a file piece by piece into a writer which doesn't have to be seekable.
+// The header and symbols come first, followed by the objects of each data chunk
+// and any other chunks, the file is completed with finish. The SIZE attribute
+// is left at zero, the total size is stored in the end chunk instead.
+pub struct S7Writer<W> {
+    writer: W,
+    size: u64,
+    data: Vec<u8>,
+    index: Vec<IndexEntry>,
+}
 
-    write_header_chunk(&mut file, entity.header_chunk);
+impl<W: Write> S7Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            size: 0,
+            data: Vec::new(),
+            index: Vec::new(),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.size += bytes.len() as u64;
+        self.writer.write_all(bytes)
+    }
+
+    pub fn write_header(
+        &mut self,
+        version: &str,
+        header: HeaderChunk,
+        symbol_chunk: Option<SymbolChunk>,
+    ) -> io::Result<()> {
+        // Magic number
+        self.write_all(&[0x0d, b'S', b'7', 0x0d])?;
+
+        // Version
+        self.write_all(version.as_bytes())?;
+        self.write_all(&[0])?;
+
+        let mut data = Vec::new();
+        write_header_chunk(&mut data, header)?;
+
+        // Symbols have to be known before the objects placing them
+        if let Some(chunk) = symbol_chunk {
+            write_symbol_chunk(&mut data, chunk)?;
+        }
+
+        self.write_all(&data)
+    }
+
+    // Objects are collected until the data chunk is flushed
+    pub fn write_object(&mut self, object: Object) {
+        write_object_data(&mut self.data, object);
+    }
+
+    pub fn flush_data_chunk(&mut self, extent: Option<Region>) -> io::Result<()> {
+        let mut data = Vec::new();
+
+        // The extent comes before any objects
+        if let Some(extent) = extent {
+            write_extent_data(&mut data, extent);
+        }
+        data.append(&mut self.data);
+
+        let mut chunk = Vec::new();
+        write_chunk(&mut chunk, "DATA", data)?;
+
+        self.index.push(IndexEntry {
+            offset: self.size,
+            size: chunk.len() as u32,
+            extent,
+        });
+
+        self.write_all(&chunk)
+    }
+
+    pub fn write_chunk(&mut self, chunk: Chunk) -> io::Result<()> {
+        let mut data = Vec::new();
+        write_chunk(&mut data, &chunk.code, chunk.data)?;
+        self.write_all(&data)
+    }
+
+    // Flushes the remaining objects and writes the end chunk, returns the
+    // total size of the file
+    pub fn finish(mut self) -> io::Result<u64> {
+        if !self.data.is_empty() {
+            self.flush_data_chunk(None)?;
+        }
+
+        // Files with several data chunks get an index of them, the end chunk
+        // then holds its offset after the total size
+        let mut end_data = Vec::new();
+        if self.index.len() > 1 {
+            let index_offset = self.size;
+            let index = std::mem::take(&mut self.index);
+            self.write_chunk(Chunk {
+                code: "INDX".to_owned(),
+                data: index_data(&index),
+            })?;
+
+            end_data.extend_from_slice(&index_offset.to_be_bytes());
+        }
+
+        let size = self.size + 16 + end_data.len() as u64;
+        end_data.splice(0..0, size.to_be_bytes().iter().copied());
+        self.write_chunk(Chunk {
+            code: "FEND".to_owned(),
+            data: end_data,
+        })?;
+        self.writer.flush()?;
+
+        Ok(size)
+    }
+}
+
+// Writes the entity without seeking, returns the total size of the file
+pub fn write_stream<W: Write>(writer: W, entity: Entity) -> io::Result<u64> {
+    let mut writer = S7Writer::new(writer);
+    writer.write_header(&entity.version, entity.header_chunk, entity.symbol_chunk)?;
 
     for chunk in entity.data_chunks {
-        write_data_chunk(&mut file, chunk);
+        for object in chunk.objects {
+            writer.write_object(object);
+        }
+        writer.flush_data_chunk(chunk.extent)?;
+    }
+
+    for chunk in entity.other_chunks {
+        writer.write_chunk(chunk)?;
     }
 
-    write_chunk(&mut file, "FEND", Vec::new());
+    writer.finish()
+}
+
+pub fn write_to<W: Write + Seek>(file: &mut W, entity: Entity) {
+    let start_pos = file.stream_position().unwrap();
+
+    // Position of the SIZE attribute value, after the magic number, the version
+    // and the chunk and attribute headers
+    let size_pos = start_pos + 4 + entity.version.len() as u64 + 1 + 16;
+
+    let file_size = write_stream(&mut *file, entity).unwrap();
 
     // Overwrite file size
-    let file_size = file.metadata().unwrap().len();
-    file.seek(SeekFrom::Start(26)).unwrap();
+    let end_pos = file.stream_position().unwrap();
+    file.seek(SeekFrom::Start(size_pos)).unwrap();
     file.write_all(&file_size.to_be_bytes()[..]).unwrap();
+    file.seek(SeekFrom::Start(end_pos)).unwrap();
 
     log::debug!("Output file has size of {} bytes", file_size);
 }
+
+pub fn write(out_path: &str, entity: Entity) {
+    let file = File::create(out_path).unwrap();
+    let mut writer = BufWriter::new(file);
+
+    write_to(&mut writer, entity);
+    writer.flush().unwrap();
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::write_stream;
+    use crate::entity::{
+        Chunk, CompoundPathObject, CopyObject, DataChunk, Entity, FillRule, HeaderAttibute,
+        HeaderChunk, LinearGradientObject, Object, PathObject, PixelBlockObject, RectObject,
+        Region, Run, RunsObject, Symbol, SymbolChunk, SymbolObject, VERSION,
+    };
+    use crate::s7_image::Rgb;
+    use crate::serialization::read::read_from;
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Entity with every kind of object, split into a chunk with an extent and
+    // one without
+    pub(crate) fn entity_with_every_object() -> Entity {
+        Entity {
+            version: VERSION.to_owned(),
+            header_chunk: HeaderChunk {
+                creation_date: Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+                width: 64,
+                height: 32,
+                background_color: Some(Rgb(10, 20, 30)),
+                other_attributes: vec![HeaderAttibute {
+                    key: "NOTE".to_owned(),
+                    val: b"note".to_vec(),
+                }],
+            },
+            symbol_chunk: Some(SymbolChunk {
+                symbols: vec![Symbol {
+                    width: 3,
+                    height: 2,
+                    mask: vec![true, false, true, false, true, false],
+                }],
+            }),
+            data_chunks: vec![
+                DataChunk {
+                    extent: Some(Region {
+                        x: 0,
+                        y: 0,
+                        width: 32,
+                        height: 32,
+                    }),
+                    objects: vec![
+                        Object::Path(PathObject {
+                            color: Rgb(255, 0, 0),
+                            points: vec![(0, 0), (10, 0), (10, 10), (0, 10)],
+                        }),
+                        Object::CompoundPath(CompoundPathObject {
+                            color: Rgb(0, 255, 0),
+                            fill_rule: FillRule::EvenOdd,
+                            rings: vec![
+                                vec![(2, 2), (20, 2), (20, 20), (2, 20)],
+                                vec![(5, 5), (8, 5), (8, 8)],
+                            ],
+                        }),
+                        Object::CompoundPath(CompoundPathObject {
+                            color: Rgb(0, 0, 255),
+                            fill_rule: FillRule::NonZero,
+                            rings: vec![vec![(1, 1)]],
+                        }),
+                        Object::Rect(RectObject {
+                            color: Rgb(1, 2, 3),
+                            x: 4,
+                            y: 5,
+                            width: 6,
+                            height: 7,
+                        }),
+                        Object::PixelBlock(PixelBlockObject {
+                            x: 20,
+                            y: 20,
+                            width: 2,
+                            height: 2,
+                            pixels: (0..12).collect(),
+                        }),
+                    ],
+                },
+                DataChunk {
+                    extent: None,
+                    objects: vec![
+                        Object::Runs(RunsObject {
+                            runs: vec![
+                                Run {
+                                    y: 3,
+                                    x_start: 40,
+                                    length: 5,
+                                    color: Rgb(9, 8, 7),
+                                },
+                                Run {
+                                    y: 300,
+                                    x_start: 1000,
+                                    length: 1,
+                                    color: Rgb(6, 5, 4),
+                                },
+                            ],
+                        }),
+                        Object::LinearGradient(LinearGradientObject {
+                            start: (33, 0),
+                            end: (63, 31),
+                            start_color: Rgb(0, 0, 0),
+                            end_color: Rgb(255, 255, 255),
+                            points: vec![(33, 0), (63, 0), (63, 31)],
+                        }),
+                        Object::Copy(CopyObject {
+                            src: Region {
+                                x: 0,
+                                y: 0,
+                                width: 16,
+                                height: 16,
+                            },
+                            dst: (40, 16),
+                        }),
+                        Object::Symbol(SymbolObject {
+                            symbol: 0,
+                            x: 50,
+                            y: 3,
+                            color: Rgb(200, 100, 0),
+                        }),
+                    ],
+                },
+            ],
+            other_chunks: vec![Chunk {
+                code: "TEXT".to_owned(),
+                data: b"text".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data = Vec::new();
+        let size = write_stream(&mut data, entity_with_every_object()).unwrap();
+        assert_eq!(size, data.len() as u64);
+
+        let entity = read_from(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(
+            format!("{:?}", entity),
+            format!("{:?}", entity_with_every_object())
+        );
+    }
+
+    #[test]
+    fn empty_entity() {
+        let mut entity = entity_with_every_object();
+        entity.symbol_chunk = None;
+        entity.data_chunks.clear();
+        entity.other_chunks.clear();
+
+        let mut data = Vec::new();
+        write_stream(&mut data, entity).unwrap();
+
+        let entity = read_from(&mut Cursor::new(&data)).unwrap();
+        assert!(entity.symbol_chunk.is_none());
+        assert!(entity.data_chunks.is_empty());
+        assert!(entity.other_chunks.is_empty());
+    }
+}
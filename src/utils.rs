@@ -1,3 +1,5 @@
+use super::entity::{HeaderChunk, Object, Region, Symbol};
+use super::s7_image::Coords;
 use image::RgbImage;
 use imageproc::point::Point;
 
@@ -10,9 +12,114 @@ pub fn blank_image(width: u16, height: u16) -> RgbImage {
     .unwrap()
 }
 
+pub fn background_image(header_chunk: &HeaderChunk) -> RgbImage {
+    RgbImage::from_pixel(
+        header_chunk.width as u32,
+        header_chunk.height as u32,
+        header_chunk.background().into(),
+    )
+}
+
 pub fn convert_points(points: Vec<(u16, u16)>) -> Vec<Point<i32>> {
     points
         .iter()
         .map(|(x, y)| Point::new(*x as i32, *y as i32))
         .collect()
 }
+
+pub fn get_bounds(points: &[Coords]) -> (Coords, Coords) {
+    let (mut min_x, mut min_y) = points[0];
+    let (mut max_x, mut max_y) = points[0];
+
+    for (x, y) in points {
+        min_x = min_x.min(*x);
+        min_y = min_y.min(*y);
+        max_x = max_x.max(*x);
+        max_y = max_y.max(*y);
+    }
+
+    ((min_x, min_y), (max_x, max_y))
+}
+
+// Bounds of the pixels the object draws to, if it draws any
+pub fn get_object_bounds(object: &Object, symbols: &[Symbol]) -> Option<(Coords, Coords)> {
+    let region_bounds = |x: u16, y: u16, width: u16, height: u16| {
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        Some((
+            (x, y),
+            (x.saturating_add(width - 1), y.saturating_add(height - 1)),
+        ))
+    };
+
+    match object {
+        Object::Path(path) if !path.points.is_empty() => Some(get_bounds(&path.points)),
+        Object::LinearGradient(gradient) if !gradient.points.is_empty() => {
+            Some(get_bounds(&gradient.points))
+        }
+        Object::CompoundPath(path) => {
+            let points: Vec<Coords> = path.rings.iter().flatten().copied().collect();
+
+            if points.is_empty() {
+                None
+            } else {
+                Some(get_bounds(&points))
+            }
+        }
+        Object::Rect(rect) => region_bounds(rect.x, rect.y, rect.width, rect.height),
+        Object::PixelBlock(block) => region_bounds(block.x, block.y, block.width, block.height),
+        Object::Copy(copy) => {
+            region_bounds(copy.dst.0, copy.dst.1, copy.src.width, copy.src.height)
+        }
+        Object::Symbol(placement) => {
+            let symbol = symbols.get(placement.symbol as usize)?;
+            region_bounds(placement.x, placement.y, symbol.width, symbol.height)
+        }
+        Object::Runs(runs) => {
+            let mut bounds: Option<(Coords, Coords)> = None;
+
+            for run in runs.runs.iter().filter(|run| run.length > 0) {
+                let run_end = run.x_start.saturating_add(run.length - 1);
+
+                bounds = Some(match bounds {
+                    Some(((min_x, min_y), (max_x, max_y))) => (
+                        (min_x.min(run.x_start), min_y.min(run.y)),
+                        (max_x.max(run_end), max_y.max(run.y)),
+                    ),
+                    None => ((run.x_start, run.y), (run_end, run.y)),
+                });
+            }
+
+            bounds
+        }
+        _ => None,
+    }
+}
+
+pub fn regions_overlap(a: Region, b: Region) -> bool {
+    let overlaps = |a_start: u16, a_length: u16, b_start: u16, b_length: u16| {
+        (a_start as u32) < b_start as u32 + b_length as u32
+            && (b_start as u32) < a_start as u32 + a_length as u32
+    };
+
+    overlaps(a.x, a.width, b.x, b.width) && overlaps(a.y, a.height, b.y, b.height)
+}
+
+// Data chunks only depend on each other when they draw to the same pixels, or
+// when one of them has no extent and may draw anywhere
+pub fn are_disjoint(extents: &[Option<Region>]) -> bool {
+    let extents: Option<Vec<Region>> = extents.iter().copied().collect();
+
+    let extents = match extents {
+        Some(extents) => extents,
+        None => return false,
+    };
+
+    extents.iter().enumerate().all(|(index, a)| {
+        extents[index + 1..]
+            .iter()
+            .all(|b| !regions_overlap(*a, *b))
+    })
+}
@@ -0,0 +1,86 @@
+use super::algorithm::decode::decode_rows;
+use super::entity::Entity;
+use deflate::write::ZlibEncoder;
+use deflate::Compression;
+use std::io::{self, Write};
+
+const IDAT_SIZE: usize = 32 * 1024;
+
+// Collects the compressed stream into IDAT chunks. The stream writer of png
+// can't be used, as it writes a stray byte at the end of each chunk and never
+// finishes the zlib stream.
+struct IdatWriter<'a, W: Write> {
+    writer: &'a mut png::Writer<W>,
+    buffer: Vec<u8>,
+}
+
+impl<'a, W: Write> IdatWriter<'a, W> {
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.writer.write_chunk(png::chunk::IDAT, &self.buffer)?;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, W: Write> Write for IdatWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= IDAT_SIZE {
+            self.write_chunk()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()
+    }
+}
+
+// Decodes the entity straight into the PNG stream, so that only a band of rows
+// is held in memory instead of the whole image
+pub fn write_png_streaming<W: Write>(writer: W, entity: &Entity) -> Result<(), png::EncodingError> {
+    let header_chunk = &entity.header_chunk;
+    let row_length = header_chunk.width as usize * 3;
+
+    let mut encoder = png::Encoder::new(
+        writer,
+        header_chunk.width as u32,
+        header_chunk.height as u32,
+    );
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    let mut stream = ZlibEncoder::new(
+        IdatWriter {
+            writer: &mut writer,
+            buffer: Vec::with_capacity(IDAT_SIZE),
+        },
+        Compression::Default,
+    );
+
+    // Each row is written with the sub filter, which subtracts the pixel to
+    // the left
+    let mut filtered = vec![0; row_length + 1];
+    filtered[0] = png::FilterType::Sub as u8;
+
+    decode_rows(entity, |rows| {
+        for row in rows.chunks(row_length.max(1)) {
+            for (index, value) in row.iter().enumerate() {
+                let left = if index >= 3 { row[index - 3] } else { 0 };
+                filtered[index + 1] = value.wrapping_sub(left);
+            }
+
+            stream.write_all(&filtered)?;
+        }
+
+        Ok::<(), io::Error>(())
+    })?;
+
+    stream.finish()?.flush()?;
+    Ok(())
+}